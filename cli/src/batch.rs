@@ -0,0 +1,347 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tracing::{error, info};
+
+use crate::channel::{unbounded, Sender};
+use crate::process::{trimmed_path, write_clips, write_montage, MontageSource};
+use crate::{Args, DetectionArgs, ProcessResult, VideoProcessor};
+
+/// file extensions picked up when a directory is given as input
+pub const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "webm", "mov"];
+
+/// Settings shared by every recording of a batch
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    pub detection: DetectionArgs,
+    /// decoding threads per recording
+    pub threads: usize,
+    /// how many recordings are processed at the same time
+    pub parallel: usize,
+    pub hw_accel: bool,
+    pub montage: Montage,
+    /// directory for the detection caches, defaults to next to each recording
+    pub cache_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Montage {
+    /// only detect, e.g. to fill the caches
+    None,
+    /// one montage per recording, next to it or in `output_dir`
+    PerFile { output_dir: Option<PathBuf> },
+    /// the clips of all recordings in input order in a single montage
+    Combined(PathBuf),
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum BatchEvent {
+    Started {
+        index: usize,
+        input: PathBuf,
+    },
+    Progress {
+        index: usize,
+        progress: f32,
+        speed: f32,
+    },
+    Finished {
+        index: usize,
+        clips: usize,
+        output: Option<PathBuf>,
+    },
+    Failed {
+        index: usize,
+        error: String,
+    },
+    MontageWritten {
+        output: PathBuf,
+    },
+}
+
+pub struct BatchItem {
+    pub input: PathBuf,
+    pub result: Result<ProcessResult, String>,
+}
+
+/// Expands directories to the videos in them and `*`/`?` wildcards in file names.
+/// Other paths are kept as is
+pub fn expand_inputs(inputs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut expanded = Vec::new();
+    for input in inputs {
+        let file_name = input
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if input.is_dir() {
+            expanded.extend(list_dir(input, is_video));
+        } else if file_name.contains(['*', '?']) {
+            let dir = match input.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            expanded.extend(list_dir(&dir, |path| {
+                path.file_name()
+                    .is_some_and(|name| wildcard_match(&file_name, &name.to_string_lossy()))
+            }));
+        } else {
+            expanded.push(input.clone());
+        }
+    }
+    expanded
+}
+
+fn list_dir(dir: &Path, filter: impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        error!("failed to read directory {}", dir.display());
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && filter(path))
+        .collect();
+    files.sort();
+    files
+}
+
+pub fn is_video(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| VIDEO_EXTENSIONS.iter().any(|v| ext.eq_ignore_ascii_case(v)))
+}
+
+/// `*` matches any number of characters, `?` exactly one
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Processes every input, `options.parallel` at a time, and writes the montage(s).
+/// A recording that fails doesn't stop the batch, results are in input order
+pub fn run_batch(
+    inputs: Vec<PathBuf>,
+    options: &BatchOptions,
+    events: Sender<BatchEvent>,
+) -> Vec<BatchItem> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<ProcessResult, String>>>> =
+        Mutex::new(vec![None; inputs.len()]);
+
+    std::thread::scope(|scope| {
+        for _ in 0..options.parallel.clamp(1, inputs.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(input) = inputs.get(index) else {
+                    break;
+                };
+                let result = process_one(index, input, options, &events);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    let items: Vec<BatchItem> = inputs
+        .into_iter()
+        .zip(results.into_inner().unwrap())
+        .map(|(input, result)| BatchItem {
+            input,
+            result: result.unwrap_or_else(|| Err("not processed".to_string())),
+        })
+        .collect();
+
+    if let Montage::Combined(output) = &options.montage {
+        let sources: Vec<MontageSource> = items
+            .iter()
+            .filter_map(|item| {
+                let result = item.result.as_ref().ok()?;
+                Some(MontageSource {
+                    input: &item.input,
+                    clips: &result.clips,
+                    keyframes: &result.keyframes,
+                })
+            })
+            .collect();
+        match write_montage(&sources, output) {
+            Ok(()) => {
+                info!("montage written to {}", output.display());
+                events
+                    .send(BatchEvent::MontageWritten {
+                        output: output.clone(),
+                    })
+                    .ok();
+            }
+            Err(err) => error!("failed to write montage: {err}"),
+        }
+    }
+
+    items
+}
+
+fn process_one(
+    index: usize,
+    input: &Path,
+    options: &BatchOptions,
+    events: &Sender<BatchEvent>,
+) -> Result<ProcessResult, String> {
+    info!("processing {}", input.display());
+    events
+        .send(BatchEvent::Started {
+            index,
+            input: input.to_path_buf(),
+        })
+        .ok();
+
+    let args = Args {
+        input: input.to_path_buf(),
+        detection: options.detection.clone(),
+        threads: options.threads,
+        cache_dir: options.cache_dir.clone(),
+    };
+    let output = match &options.montage {
+        Montage::PerFile { output_dir } => {
+            let output = trimmed_path(input);
+            Some(match output_dir {
+                Some(dir) => dir.join(output.file_name().unwrap()),
+                None => output,
+            })
+        }
+        Montage::None | Montage::Combined(_) => None,
+    };
+
+    // the processor panics on unreadable files, run it on its own thread so the batch continues
+    let (progress_tx, progress_rx) = unbounded();
+    let (frame_tx, _) = unbounded();
+    let hw_accel = options.hw_accel;
+    let thread_output = output.clone();
+    let handle = std::thread::spawn(move || {
+        let result = VideoProcessor::open(args.clone(), progress_tx, frame_tx).process(hw_accel);
+        if let Some(output) = thread_output.filter(|_| !result.clips.is_empty()) {
+            write_clips(&args.input, &output, &result.clips, &result.keyframes);
+        }
+        result
+    });
+    while let Ok(progress) = progress_rx.recv() {
+        events
+            .send(BatchEvent::Progress {
+                index,
+                progress: progress.progress,
+                speed: progress.speed,
+            })
+            .ok();
+    }
+
+    match handle.join() {
+        Ok(result) => {
+            events
+                .send(BatchEvent::Finished {
+                    index,
+                    clips: result.clips.len(),
+                    output: output.filter(|_| !result.clips.is_empty()),
+                })
+                .ok();
+            Ok(result)
+        }
+        Err(_) => {
+            let error = format!("failed to process {}", input.display());
+            error!("{error}");
+            events
+                .send(BatchEvent::Failed {
+                    index,
+                    error: error.clone(),
+                })
+                .ok();
+            Err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_video::temp_path;
+
+    #[test]
+    fn wildcards_match_file_names() {
+        for (pattern, name, matches) in [
+            ("*.mp4", "match.mp4", true),
+            ("*.mp4", ".mp4", true),
+            ("*.mp4", "match.mkv", false),
+            ("match *.mp4", "match 2.mp4", true),
+            ("match*2.mp4", "match 1 2.mp4", true),
+            ("match*2.mp4", "match 1 3.mp4", false),
+            ("match*", "match 1.mp4", true),
+            ("match*", "other.mp4", false),
+            ("*a*a*", "banana", true),
+            ("*a*a*a*a*", "banana", false),
+            ("match ?.mp4", "match 1.mp4", true),
+            ("match ?.mp4", "match 10.mp4", false),
+            ("*", "", true),
+            ("?", "", false),
+            ("match.mp4", "match.mp4", true),
+            ("match.mp4", "Match.mp4", false),
+        ] {
+            assert_eq!(
+                wildcard_match(pattern, name),
+                matches,
+                "{pattern:?} on {name:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn inputs_are_expanded() {
+        let dir = temp_path("expand_inputs");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(dir.join("nested.mp4")).unwrap();
+        for name in ["match 2.mp4", "match 1.MKV", "notes.txt", "other.mp4"] {
+            std::fs::write(dir.join(name), []).unwrap();
+        }
+        let missing = dir.join("missing.mp4");
+
+        for (inputs, expanded) in [
+            // directories are listed sorted, only videos and no subdirectories
+            (
+                vec![dir.clone()],
+                vec!["match 1.MKV", "match 2.mp4", "other.mp4"],
+            ),
+            (vec![dir.join("*.mp4")], vec!["match 2.mp4", "other.mp4"]),
+            (vec![dir.join("match*")], vec!["match 1.MKV", "match 2.mp4"]),
+            (vec![dir.join("*s.txt")], vec!["notes.txt"]),
+            (vec![dir.join("*.avi")], vec![]),
+            (
+                vec![dir.join("other.mp4"), dir.join("match ?.mp4")],
+                vec!["other.mp4", "match 2.mp4"],
+            ),
+        ] {
+            let expected: Vec<PathBuf> = expanded.iter().map(|name| dir.join(name)).collect();
+            assert_eq!(expand_inputs(&inputs), expected, "{inputs:?}");
+        }
+        // paths without wildcards are kept even if they don't exist, processing reports them
+        assert_eq!(expand_inputs(&[missing.clone()]), [missing]);
+    }
+}
@@ -1,77 +1,162 @@
-use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
 
 use bincode::{Decode, Encode};
 
+use crate::process::Detection;
+use crate::waveform::Waveform;
 use crate::Args;
 
-#[derive(Hash, Eq, PartialEq, Decode, Encode)]
-pub struct CacheKey {
-    pub include_spectating: bool,
-    pub include_assists: bool,
-}
+/// bump whenever the layout of [`EclipperCache`] changes so stale caches are ignored
+const CACHE_VERSION: u32 = 13;
 
 #[derive(Decode, Encode)]
 pub struct EclipperCache {
+    pub version: u32,
     pub file_size: u64,
+    /// `None` until detection has run for the file
+    pub detections: Option<DetectionCache>,
+    /// computed by the editor, whether or not the file was processed
+    pub waveform: Option<Waveform>,
+}
+
+#[derive(Decode, Encode)]
+pub struct DetectionCache {
     pub keyframes: Box<[f64]>,
-    pub clips: HashMap<CacheKey, Box<[[f64; 2]]>>,
+    /// raw detection hits for every rule, independent of the include_* toggles and clip durations
+    pub detections: Box<[Detection]>,
 }
 
-#[allow(unused)]
-pub fn cache_clips(clips: Box<[[f64; 2]]>, keyframes: Box<[f64]>, args: &Args) {
-    let Ok(metadata) = std::fs::metadata(&args.input) else {
-        return;
-    };
-    let mut cache = if let Some(cache) = clips_from_cache(&args.input) {
-        if cache.file_size == metadata.len() {
-            cache
-        } else {
-            EclipperCache {
-                file_size: metadata.len(),
-                keyframes,
-                clips: Default::default(),
-            }
-        }
-    } else {
-        EclipperCache {
-            file_size: metadata.len(),
+/// held while a cache file is read, changed and written back, so detections and the waveform
+/// stored at the same time don't overwrite each other
+static CACHE_LOCK: Mutex<()> = Mutex::new(());
+
+pub fn cache_detections(detections: Box<[Detection]>, keyframes: Box<[f64]>, args: &Args) {
+    update_cache(&args.input, args.cache_dir.as_deref(), |cache| {
+        cache.detections = Some(DetectionCache {
             keyframes,
-            clips: Default::default(),
-        }
-    };
+            detections,
+        });
+    });
+}
 
-    let cache_key = CacheKey {
-        include_spectating: args.include_spectating,
-        include_assists: args.include_assists,
-    };
+pub fn detections_from_cache(input: &Path, cache_dir: Option<&Path>) -> Option<DetectionCache> {
+    read_cache(input, cache_dir)?.detections
+}
 
-    cache.clips.insert(cache_key, clips);
+pub fn cache_waveform(input: &Path, cache_dir: Option<&Path>, waveform: Waveform) {
+    update_cache(input, cache_dir, |cache| cache.waveform = Some(waveform));
+}
 
-    let mut cache_file = std::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(args.input.with_extension("eclipper"));
+pub fn waveform_from_cache(input: &Path, cache_dir: Option<&Path>) -> Option<Waveform> {
+    read_cache(input, cache_dir)?.waveform
+}
 
-    let Ok(mut cache_file) = cache_file else {
+/// Reads the cache of `input`, or starts an empty one, and writes it back after `update`
+fn update_cache(input: &Path, cache_dir: Option<&Path>, update: impl FnOnce(&mut EclipperCache)) {
+    let Ok(metadata) = std::fs::metadata(input) else {
         return;
     };
-    bincode::encode_into_std_write(cache, &mut cache_file, bincode::config::standard());
+    let _lock = CACHE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut cache = read_cache(input, cache_dir).unwrap_or(EclipperCache {
+        version: CACHE_VERSION,
+        file_size: metadata.len(),
+        detections: None,
+        waveform: None,
+    });
+    update(&mut cache);
+    write_cache(input, cache_dir, cache);
+}
+
+/// `<input>.eclipper`, in `cache_dir` if given instead of next to the input
+fn cache_path(input: &Path, cache_dir: Option<&Path>) -> PathBuf {
+    let path = input.with_extension("eclipper");
+    match (cache_dir, path.file_name()) {
+        (Some(dir), Some(name)) => dir.join(name),
+        _ => path,
+    }
 }
 
-pub fn clips_from_cache(input: &Path) -> Option<EclipperCache> {
+fn read_cache(input: &Path, cache_dir: Option<&Path>) -> Option<EclipperCache> {
     let Ok(metadata) = std::fs::metadata(input) else {
         return None;
     };
-    let mut cache_file = std::fs::File::open(input.with_extension("eclipper")).ok()?;
+    let mut cache_file = std::fs::File::open(cache_path(input, cache_dir)).ok()?;
 
     let decoded: EclipperCache =
         bincode::decode_from_std_read(&mut cache_file, bincode::config::standard()).ok()?;
 
-    if decoded.file_size != metadata.len() {
+    if decoded.version != CACHE_VERSION || decoded.file_size != metadata.len() {
         return None;
     }
 
     Some(decoded)
 }
+
+/// written to a temporary file first so the cache is never read half written
+fn write_cache(input: &Path, cache_dir: Option<&Path>, cache: EclipperCache) {
+    let path = cache_path(input, cache_dir);
+    let temp_path = path.with_extension("eclipper.tmp");
+    let Ok(mut cache_file) = std::fs::File::create(&temp_path) else {
+        return;
+    };
+    let written =
+        bincode::encode_into_std_write(cache, &mut cache_file, bincode::config::standard());
+    drop(cache_file);
+    if written.is_err() || std::fs::rename(&temp_path, &path).is_err() {
+        std::fs::remove_file(&temp_path).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::EventKind;
+    use crate::test_video::temp_path;
+
+    #[test]
+    fn detections_and_waveform_are_cached_independently() {
+        let input = temp_path("cache_input.mp4");
+        std::fs::write(&input, [0; 100]).unwrap();
+        let cache_dir = temp_path("cache_dir");
+        std::fs::remove_dir_all(&cache_dir).ok();
+        std::fs::create_dir(&cache_dir).unwrap();
+        let cache_dir = Some(cache_dir.as_path());
+
+        // the editor extracts the waveform before the file is processed
+        let waveform = Waveform {
+            streams: Box::new([]),
+        };
+        cache_waveform(&input, cache_dir, waveform.clone());
+        assert!(waveform_from_cache(&input, cache_dir).is_some());
+        assert!(detections_from_cache(&input, cache_dir).is_none());
+        assert!(!cache_path(&input, None).exists());
+
+        update_cache(&input, cache_dir, |cache| {
+            cache.detections = Some(DetectionCache {
+                keyframes: Box::new([0., 2.]),
+                detections: Box::new([Detection {
+                    time: 1.,
+                    kind: EventKind::Elimination,
+                    spectating: false,
+                    states: Vec::new(),
+                    ocr: None,
+                    count: 1,
+                    rows: Vec::new(),
+                }]),
+            });
+        });
+        cache_waveform(&input, cache_dir, waveform);
+        let cached = detections_from_cache(&input, cache_dir).unwrap();
+        assert_eq!(*cached.keyframes, [0., 2.]);
+        assert_eq!(cached.detections.len(), 1);
+        assert!(waveform_from_cache(&input, cache_dir).is_some());
+
+        // a changed input invalidates the cache
+        std::fs::write(&input, [0; 200]).unwrap();
+        assert!(detections_from_cache(&input, cache_dir).is_none());
+
+        std::fs::remove_dir_all(cache_dir.unwrap()).ok();
+        std::fs::remove_file(&input).ok();
+    }
+}
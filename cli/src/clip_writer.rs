@@ -1,10 +1,10 @@
 use crate::ffmpeg;
 use crate::video_decoder::t_to_secs;
-use ffmpeg_next::ffi::{av_seek_frame, AVSEEK_FLAG_ANY, AV_TIME_BASE_Q};
+use ffmpeg_next::ffi::{av_seek_frame, AVCodecParameters, AVSEEK_FLAG_ANY, AV_TIME_BASE_Q};
 use ffmpeg_next::format::context;
 use ffmpeg_next::{codec, encoder, format, log, media, Rational};
 use std::ffi::c_int;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 pub struct ClipWriter {
     end_ts: i64,
@@ -18,6 +18,8 @@ pub struct ClipWriter {
     stream_mapping: Vec<isize>,
     /// keyframe timestamps in video_stream_timebase
     keyframes: Option<Box<[i64]>>,
+    /// time base packets of each output stream are written in, those of the first input
+    output_time_bases: Vec<Rational>,
 }
 
 impl ClipWriter {
@@ -33,6 +35,7 @@ impl ClipWriter {
         let video_stream_timebase = video_stream.time_base();
 
         let mut stream_mapping = vec![0_isize; ictx.nb_streams() as _];
+        let mut output_time_bases = Vec::new();
         let mut ost_index = 0;
         for (ist_index, ist) in ictx.streams().enumerate() {
             let ist_medium = ist.parameters().medium();
@@ -48,6 +51,7 @@ impl ClipWriter {
             let mut ost = octx.add_stream(encoder::find(codec::Id::None)).unwrap();
             ost.set_parameters(ist.parameters());
             ost.set_time_base(ist.time_base());
+            output_time_bases.push(ist.time_base());
             ist.start_time();
             // We need to set codec_tag to 0 lest we run into incompatible codec tag
             // issues when muxing into a different container format. Unfortunately
@@ -68,9 +72,64 @@ impl ClipWriter {
             video_stream_timebase,
             stream_mapping,
             keyframes: None,
+            output_time_bases,
         }
     }
 
+    /// Continues writing clips from another recording. Its streams are mapped in order to the
+    /// output streams of the same type, so the recordings must have the same stream layout
+    /// and codec parameters, see [`can_stream_copy`].
+    pub fn set_input(&mut self, input_file: &PathBuf) -> Result<(), ffmpeg::Error> {
+        let ictx = format::input(input_file)?;
+        let video_stream = ictx
+            .streams()
+            .best(media::Type::Video)
+            .ok_or(ffmpeg::Error::StreamNotFound)?;
+        let video_stream_index = video_stream.index();
+        let video_stream_timebase = video_stream.time_base();
+
+        let output_media: Vec<media::Type> = self
+            .octx
+            .streams()
+            .map(|ost| ost.parameters().medium())
+            .collect();
+        let mut stream_mapping = vec![-1_isize; ictx.nb_streams() as _];
+        let mut ost_index = 0;
+        for (ist_index, ist) in ictx.streams().enumerate() {
+            let ist_medium = ist.parameters().medium();
+            if ist_medium != media::Type::Audio
+                && ist_medium != media::Type::Video
+                && ist_medium != media::Type::Subtitle
+            {
+                continue;
+            }
+            if output_media.get(ost_index) != Some(&ist_medium) {
+                return Err(ffmpeg::Error::StreamNotFound);
+            }
+            let ost = self.octx.stream(ost_index).unwrap();
+            if !parameters_match(&ist.parameters(), &ost.parameters()) {
+                return Err(ffmpeg::Error::InvalidData);
+            }
+            stream_mapping[ist_index] = ost_index as isize;
+            ost_index += 1;
+        }
+        if ost_index != output_media.len() {
+            return Err(ffmpeg::Error::StreamNotFound);
+        }
+
+        self.end_ts = convert_timebase(
+            self.end_ts,
+            self.video_stream_timebase,
+            video_stream_timebase,
+        );
+        self.ictx = ictx;
+        self.video_stream_index = video_stream_index;
+        self.video_stream_timebase = video_stream_timebase;
+        self.stream_mapping = stream_mapping;
+        self.keyframes = None;
+        Ok(())
+    }
+
     pub fn seek(&mut self, timestamp: i64) {
         unsafe {
             av_seek_frame(
@@ -130,8 +189,10 @@ impl ClipWriter {
 
             let to_ts = secs_to_ts(to_secs, stream.time_base());
             let from_ts = secs_to_ts(from_secs, stream.time_base());
+            // the pts is rewritten to montage time below, which can be past the input's clip times
+            let input_pts = packet.pts();
 
-            if packet.pts().unwrap_or(0) >= from_ts && packet.pts().unwrap_or(i64::MAX) < to_ts {
+            if input_pts.unwrap_or(0) >= from_ts && input_pts.unwrap_or(i64::MAX) < to_ts {
                 if let Some(pts) = packet.pts() {
                     let pts = if stream.index() == self.video_stream_index {
                         pts
@@ -164,17 +225,26 @@ impl ClipWriter {
                 packet.set_dts(packet.dts().map(|dts| dts + difference));
                 // let ost = self.octx.stream(ost_index as _).unwrap();
                 // packet.rescale_ts(stream.time_base(), ost.time_base());
+                // only differs after switching to an input with other time bases
+                packet.rescale_ts(
+                    stream.time_base(),
+                    self.output_time_bases[ost_index as usize],
+                );
                 packet.set_position(-1);
                 packet.set_stream(ost_index as _);
                 packet.write_interleaved(&mut self.octx).unwrap();
             }
 
-            if t_to_secs(packet.pts().unwrap_or(0), stream.time_base()) > to_secs + 10. {
+            if t_to_secs(input_pts.unwrap_or(0), stream.time_base()) > to_secs + 10. {
                 break;
             }
         }
 
-        let duration = last_pts - first_pts.unwrap() + last_duration;
+        let Some(first_pts) = first_pts else {
+            eprintln!("Warning: no packets between {from_secs} and {to_secs}");
+            return;
+        };
+        let duration = last_pts - first_pts + last_duration;
         self.end_ts += duration;
     }
 
@@ -209,6 +279,61 @@ impl ClipWriter {
     }
 }
 
+/// Whether the clips of all `inputs` can be written to one output without re-encoding,
+/// i.e. they have the same streams with the same codec parameters
+pub fn can_stream_copy(inputs: &[&Path]) -> Result<bool, ffmpeg::Error> {
+    let mut first: Option<Vec<codec::Parameters>> = None;
+    for input in inputs {
+        let ictx = format::input(input)?;
+        let parameters: Vec<codec::Parameters> = ictx
+            .streams()
+            .map(|stream| stream.parameters())
+            .filter(|parameters| {
+                matches!(
+                    parameters.medium(),
+                    media::Type::Audio | media::Type::Video | media::Type::Subtitle
+                )
+            })
+            .collect();
+        match &first {
+            None => first = Some(parameters),
+            Some(first) => {
+                if first.len() != parameters.len()
+                    || first
+                        .iter()
+                        .zip(parameters.iter())
+                        .any(|(a, b)| !parameters_match(a, b))
+                {
+                    return Ok(false);
+                }
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Packets of streams with matching parameters can be muxed into the same output stream.
+/// The extradata holds e.g. the h264 sps/pps, so encoder settings have to match as well
+fn parameters_match(a: &codec::Parameters, b: &codec::Parameters) -> bool {
+    if a.medium() != b.medium() || a.id() != b.id() {
+        return false;
+    }
+    let (a, b) = unsafe { (&*a.as_ptr(), &*b.as_ptr()) };
+    let extradata = |p: &AVCodecParameters| unsafe {
+        if p.extradata.is_null() {
+            &[][..]
+        } else {
+            std::slice::from_raw_parts(p.extradata, p.extradata_size as usize)
+        }
+    };
+    a.format == b.format
+        && a.width == b.width
+        && a.height == b.height
+        && a.sample_rate == b.sample_rate
+        && a.frame_size == b.frame_size
+        && extradata(a) == extradata(b)
+}
+
 pub fn secs_to_ts(secs: f64, time_base: Rational) -> i64 {
     (secs / (time_base.numerator() as f64 / time_base.denominator() as f64)) as i64
 }
@@ -217,3 +342,41 @@ pub fn convert_timebase(ts: i64, from_base: Rational, to_base: Rational) -> i64
     let secs = t_to_secs(ts, from_base);
     secs_to_ts(secs, to_base)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_video::{frame_luma, temp_path, write_video};
+    use crate::VideoDecoder;
+
+    #[test]
+    fn writes_a_montage_from_two_sources() {
+        let first = temp_path("montage_first.mp4");
+        let second = temp_path("montage_second.mp4");
+        let output = temp_path("montage_output.mp4");
+        write_video(&first, 200, |_| 40, 1).unwrap();
+        write_video(&second, 100, |_| 200, 1).unwrap();
+
+        let mut writer = ClipWriter::new(&first, &output);
+        // long enough for the montage time to be past the clips of the second recording
+        writer.write_clip(2., 16.);
+        writer.set_input(&second).unwrap();
+        writer.write_clip(1., 3.);
+        writer.write_clip(5., 7.);
+        writer.write_trailer();
+
+        let mut decoder = VideoDecoder::open(&output, false).unwrap();
+        let lumas: Vec<u8> = decoder
+            .decode_iter()
+            .map(|frame| frame_luma(&frame))
+            .collect();
+        let first_frames = lumas.iter().take_while(|luma| **luma < 128).count();
+        assert_eq!(first_frames, 140);
+        assert_eq!(lumas.len() - first_frames, 40);
+        assert!((decoder.video_duration() - 18.).abs() < 0.5);
+
+        for path in [first, second, output] {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
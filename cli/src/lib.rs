@@ -1,17 +1,40 @@
+pub mod batch;
 mod cache_clips;
 mod clip_writer;
+pub mod live;
+pub mod match_segment;
+mod montage_encoder;
+pub mod multi_kill;
+pub mod ocr;
 mod process;
 pub mod process_frame;
+pub mod project_file;
+pub mod rule_pack;
+mod template;
+#[cfg(test)]
+mod test_video;
 mod video_decoder;
+pub mod watch;
+pub mod waveform;
 
 pub use crossbeam_channel as channel;
 pub use ffmpeg_next as ffmpeg;
+pub use process::clips_from_detections;
+pub use process::parse_event_seconds;
+pub use process::trimmed_path;
 pub use process::write_clips;
+pub use process::write_montage;
 pub use process::Args;
+pub use process::Detection;
+pub use process::DetectionArgs;
+pub use process::EventKind;
+pub use process::HudState;
+pub use process::MontageSource;
 pub use process::PreviewFrame;
 pub use process::ProcessResult;
 pub use process::SearchParam;
 pub use process::VideoProcessor;
+pub use process::DEFAULT_MULTI_KILL_WINDOW;
 pub use process::SEARCH_PARAMS;
 pub use tesseract;
 pub use video_decoder::VideoDecoder;
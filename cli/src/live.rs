@@ -0,0 +1,420 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use ffmpeg_next::{codec, encoder, format, media, Dictionary, Packet};
+use tracing::{error, info};
+
+use crate::channel::{bounded, Sender};
+use crate::match_segment::segment_matches;
+use crate::multi_kill::clip_streaks;
+use crate::process::{clips_from_detections, get_clip_times, write_montage, MontageSource, Source};
+use crate::video_decoder::t_to_secs;
+use crate::{ffmpeg, Args, PreviewFrame, ProcessResult};
+
+/// protocols accepted instead of a file path
+const PROTOCOLS: &[&str] = &["udp", "rtp", "srt", "rtmp", "tcp"];
+/// microseconds without data after which a udp stream is considered finished,
+/// the sender has to start within this time as well
+const UDP_TIMEOUT: &str = "30000000";
+
+/// Whether `input` is a network url like `udp://0.0.0.0:1234` or `srt://:9000` instead of a file
+pub fn is_network_url(input: &Path) -> bool {
+    let input = input.to_string_lossy();
+    input
+        .split_once("://")
+        .is_some_and(|(protocol, _)| PROTOCOLS.contains(&protocol))
+}
+
+/// The eclipper is always the receiving end, OBS or ffmpeg push the stream to it
+pub fn network_options(url: &str) -> Dictionary<'static> {
+    let mut options = Dictionary::new();
+    match url.split_once("://").map(|(protocol, _)| protocol) {
+        Some("rtmp") | Some("tcp") => options.set("listen", "1"),
+        Some("srt") if !url.contains("mode=") => options.set("mode", "listener"),
+        Some("udp") | Some("rtp") => {
+            options.set("timeout", UDP_TIMEOUT);
+            options.set("overrun_nonfatal", "1");
+            options.set("fifo_size", "1000000");
+        }
+        _ => {}
+    }
+    options
+}
+
+/// Settings for processing a live stream
+#[derive(Debug, Clone)]
+pub struct LiveOptions {
+    /// where the received stream is buffered
+    pub buffer_dir: PathBuf,
+    /// seconds of the stream kept on disk, older segments are deleted unless they contain a clip
+    pub window: f64,
+    /// seconds per buffer file, a new one is started at the first keyframe after this
+    pub segment_duration: f64,
+}
+
+/// A file of the rolling buffer
+pub struct Segment {
+    pub path: PathBuf,
+    /// stream time of the first keyframe
+    pub start: f64,
+    /// stream time where the next segment starts or of the last packet
+    pub end: f64,
+    pub keyframes: Vec<f64>,
+}
+
+/// Copies the packets of a live stream into a rolling window of mkv files so detected moments
+/// can be written out losslessly once the stream ends. Timestamps are kept as received, so
+/// times in the segments are the same as in the stream
+pub struct RollingBuffer {
+    options: LiveOptions,
+    segments: VecDeque<Segment>,
+    /// the newest segment while it is written
+    output: Option<format::context::Output>,
+    /// maps input stream index to output stream index of the current segment
+    stream_mapping: Vec<Option<usize>>,
+    video_stream_index: Option<usize>,
+    /// clip ranges whose segments must not be deleted
+    keep: Vec<[f64; 2]>,
+    /// segments started so far, used for the file names
+    count: usize,
+}
+
+impl RollingBuffer {
+    pub fn new(options: LiveOptions) -> std::io::Result<RollingBuffer> {
+        std::fs::create_dir_all(&options.buffer_dir)?;
+        Ok(RollingBuffer {
+            options,
+            segments: VecDeque::new(),
+            output: None,
+            stream_mapping: Vec::new(),
+            video_stream_index: None,
+            keep: Vec::new(),
+            count: 0,
+        })
+    }
+
+    /// Buffers a packet read from `ictx`. Packets before the first video keyframe are dropped
+    pub fn write(
+        &mut self,
+        ictx: &format::context::Input,
+        packet: &Packet,
+    ) -> Result<(), ffmpeg::Error> {
+        let Some(stream) = ictx.stream(packet.stream()) else {
+            return Ok(());
+        };
+        let time_base = stream.time_base();
+        let time = packet.pts().map(|pts| t_to_secs(pts, time_base));
+
+        if self.video_stream_index.is_none() {
+            self.video_stream_index = ictx.streams().best(media::Type::Video).map(|s| s.index());
+        }
+        if Some(stream.index()) == self.video_stream_index && packet.is_key() {
+            if let Some(time) = time {
+                let full = self.segments.back().map_or(true, |segment| {
+                    time - segment.start >= self.options.segment_duration
+                });
+                if self.output.is_none() || full {
+                    self.start_segment(ictx, time)?;
+                }
+                self.segments.back_mut().unwrap().keyframes.push(time);
+            }
+        }
+
+        let Some(octx) = &mut self.output else {
+            return Ok(());
+        };
+        let Some(ost_index) = self.stream_mapping.get(stream.index()).copied().flatten() else {
+            return Ok(());
+        };
+        let ost_time_base = octx.stream(ost_index).unwrap().time_base();
+        let mut packet = packet.clone();
+        packet.rescale_ts(time_base, ost_time_base);
+        packet.set_position(-1);
+        packet.set_stream(ost_index);
+        packet.write_interleaved(octx)?;
+
+        if let (Some(time), Some(segment)) = (time, self.segments.back_mut()) {
+            segment.end = segment.end.max(time);
+        }
+        Ok(())
+    }
+
+    /// Keeps the segments overlapping `range` even once they are outside the window
+    pub fn keep(&mut self, range: [f64; 2]) {
+        self.keep.push(range);
+    }
+
+    fn start_segment(
+        &mut self,
+        ictx: &format::context::Input,
+        start: f64,
+    ) -> Result<(), ffmpeg::Error> {
+        self.finish()?;
+        if let Some(previous) = self.segments.back_mut() {
+            previous.end = start;
+        }
+        self.prune(start);
+
+        self.count += 1;
+        let path = self
+            .options
+            .buffer_dir
+            .join(format!("segment_{:05}.mkv", self.count));
+        let mut octx = format::output(&path)?;
+        self.stream_mapping = vec![None; ictx.nb_streams() as _];
+        let mut ost_index = 0;
+        for (ist_index, ist) in ictx.streams().enumerate() {
+            let ist_medium = ist.parameters().medium();
+            if ist_medium != media::Type::Audio
+                && ist_medium != media::Type::Video
+                && ist_medium != media::Type::Subtitle
+            {
+                continue;
+            }
+            self.stream_mapping[ist_index] = Some(ost_index);
+            ost_index += 1;
+            let mut ost = octx.add_stream(encoder::find(codec::Id::None))?;
+            ost.set_parameters(ist.parameters());
+            ost.set_time_base(ist.time_base());
+            unsafe {
+                (*ost.parameters().as_mut_ptr()).codec_tag = 0;
+            }
+        }
+        octx.write_header()?;
+
+        self.output = Some(octx);
+        self.segments.push_back(Segment {
+            path,
+            start,
+            end: start,
+            keyframes: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// deletes segments that ended more than the window before `now` and contain no clip
+    fn prune(&mut self, now: f64) {
+        let window = self.options.window;
+        let keep = &self.keep;
+        self.segments.retain(|segment| {
+            let needed = now - segment.end < window
+                || keep
+                    .iter()
+                    .any(|range| range[0] < segment.end && range[1] > segment.start);
+            if !needed {
+                std::fs::remove_file(&segment.path).ok();
+            }
+            needed
+        });
+    }
+
+    /// Finishes the segment that is currently written
+    pub fn finish(&mut self) -> Result<(), ffmpeg::Error> {
+        match self.output.take() {
+            Some(mut octx) => octx.write_trailer(),
+            None => Ok(()),
+        }
+    }
+
+    /// keyframes of all buffered segments
+    pub fn keyframes(&self) -> Box<[f64]> {
+        self.segments
+            .iter()
+            .flat_map(|segment| segment.keyframes.iter().copied())
+            .collect()
+    }
+
+    /// stream time of the end of the newest segment
+    pub fn end(&self) -> f64 {
+        self.segments.back().map_or(0., |segment| segment.end)
+    }
+
+    /// Writes `clips` from the buffered segments into a single montage.
+    /// Parts of clips that aren't buffered anymore are skipped
+    pub fn write_clips(
+        &mut self,
+        clips: &[[f64; 2]],
+        output: &PathBuf,
+    ) -> Result<(), ffmpeg::Error> {
+        self.finish()?;
+
+        // consecutive parts in the same segment are written as one montage source
+        let mut parts: Vec<(usize, Vec<[f64; 2]>)> = Vec::new();
+        for clip in clips {
+            for (index, segment) in self.segments.iter().enumerate() {
+                if clip[0] >= segment.end || clip[1] <= segment.start {
+                    continue;
+                }
+                let part = [clip[0].max(segment.start), clip[1].min(segment.end)];
+                match parts.last_mut() {
+                    Some((last, ranges)) if *last == index => ranges.push(part),
+                    _ => parts.push((index, vec![part])),
+                }
+            }
+        }
+        let sources: Vec<MontageSource> = parts
+            .iter()
+            .map(|(index, clips)| {
+                let segment = &self.segments[*index];
+                MontageSource {
+                    input: &segment.path,
+                    clips,
+                    keyframes: &segment.keyframes,
+                }
+            })
+            .collect();
+        write_montage(&sources, output)
+    }
+
+    /// Deletes all segments
+    pub fn clear(&mut self) {
+        self.finish().ok();
+        for segment in self.segments.drain(..) {
+            std::fs::remove_file(&segment.path).ok();
+        }
+    }
+}
+
+/// Detects events in a live stream received on `args.input` until the sender stops.
+///
+/// Preview frames of detections are sent as they are found. The stream is buffered in
+/// `options.buffer_dir`, the returned buffer can write the clips out with
+/// [`RollingBuffer::write_clips`]
+pub fn process_live(
+    args: Args,
+    options: LiveOptions,
+    frame_tx: Sender<PreviewFrame>,
+    hw_accel: bool,
+) -> Result<(ProcessResult, RollingBuffer), String> {
+    let buffer = RollingBuffer::new(options)
+        .map_err(|err| format!("failed to create the buffer directory: {err}"))?;
+    let buffer = Arc::new(Mutex::new(buffer));
+    info!("waiting for a stream on {}", args.input.display());
+
+    // there is no end to measure progress against
+    let (progress_tx, _) = bounded::<f32>(1);
+    let thread_args = args.clone();
+    let thread_buffer = buffer.clone();
+    let handle = std::thread::spawn(move || {
+        get_clip_times(
+            thread_args,
+            0.,
+            0.,
+            f64::INFINITY,
+            progress_tx,
+            frame_tx,
+            hw_accel,
+            Source::Live(thread_buffer),
+        )
+    });
+    let detections = handle
+        .join()
+        .map_err(|_| "processing thread panicked".to_string())?
+        .map_err(|err| format!("failed to process stream: {err}"))?
+        .into_boxed_slice();
+
+    let Ok(buffer) = Arc::try_unwrap(buffer) else {
+        unreachable!("the decoder holding the buffer is dropped");
+    };
+    let mut buffer = buffer.into_inner().unwrap();
+    if let Err(err) = buffer.finish() {
+        error!("failed to finish buffer segment: {err}");
+    }
+    info!("stream ended after {:.1}s", buffer.end());
+
+    let keyframes = buffer.keyframes();
+    let clips = clips_from_detections(&detections, &keyframes, &args);
+    let streaks = clip_streaks(&clips, &detections, args.detection.multi_kill_window);
+    let matches = segment_matches(&detections, buffer.end());
+    let result = ProcessResult {
+        clips,
+        detections,
+        keyframes,
+        input_duration: buffer.end(),
+        streaks,
+        matches,
+    };
+    Ok((result, buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_video::{temp_path, write_video};
+
+    #[test]
+    fn network_urls_are_told_from_paths() {
+        for url in [
+            "udp://0.0.0.0:1234",
+            "srt://:9000",
+            "rtmp://0.0.0.0/live",
+            "tcp://:5000",
+        ] {
+            assert!(is_network_url(Path::new(url)), "{url}");
+        }
+        for path in [
+            "recording.mp4",
+            "C:\\videos\\udp.mkv",
+            "file://recording.mp4",
+            "udp",
+        ] {
+            assert!(!is_network_url(Path::new(path)), "{path}");
+        }
+    }
+
+    #[test]
+    fn network_options_make_the_eclipper_listen() {
+        assert_eq!(
+            network_options("rtmp://0.0.0.0/live").get("listen"),
+            Some("1")
+        );
+        assert_eq!(network_options("tcp://:5000").get("listen"), Some("1"));
+        assert_eq!(network_options("srt://:9000").get("mode"), Some("listener"));
+        assert_eq!(
+            network_options("srt://host:9000?mode=caller").get("mode"),
+            None
+        );
+
+        let udp = network_options("udp://0.0.0.0:1234");
+        assert_eq!(udp.get("timeout"), Some(UDP_TIMEOUT));
+        assert_eq!(udp.get("overrun_nonfatal"), Some("1"));
+        assert_eq!(udp.get("listen"), None);
+    }
+
+    #[test]
+    fn old_segments_are_pruned_unless_kept() {
+        let input = temp_path("rolling_input.mkv");
+        write_video(&input, 100, |n| n as u8, 0).unwrap();
+        let buffer_dir = temp_path("rolling_buffer");
+        std::fs::remove_dir_all(&buffer_dir).ok();
+
+        let mut buffer = RollingBuffer::new(LiveOptions {
+            buffer_dir: buffer_dir.clone(),
+            window: 3.,
+            segment_duration: 2.,
+        })
+        .unwrap();
+        buffer.keep([0.5, 1.]);
+        let mut ictx = format::input(&input).unwrap();
+        let packets: Vec<Packet> = ictx.packets().map(|(_, packet)| packet).collect();
+        for packet in &packets {
+            buffer.write(&ictx, packet).unwrap();
+        }
+        buffer.finish().unwrap();
+
+        // a keyframe every second, a segment every two
+        let starts: Vec<f64> = buffer.segments.iter().map(|s| s.start).collect();
+        assert_eq!(starts, [0., 4., 6., 8.]);
+        assert_eq!(buffer.segments[0].end, 2.);
+        assert_eq!(*buffer.keyframes(), [0., 1., 4., 5., 6., 7., 8., 9.]);
+        assert!((buffer.end() - 9.9).abs() < 0.05, "{}", buffer.end());
+        let files = std::fs::read_dir(&buffer_dir).unwrap().count();
+        assert_eq!(files, 4);
+
+        buffer.clear();
+        assert_eq!(std::fs::read_dir(&buffer_dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(&buffer_dir).ok();
+        std::fs::remove_file(&input).ok();
+    }
+}
@@ -0,0 +1,383 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::ValueHint::FilePath;
+use clap::{Parser, Subcommand};
+use tracing::{error, info};
+
+use the_finals_eclipper::batch::{expand_inputs, run_batch, BatchEvent, BatchOptions, Montage};
+use the_finals_eclipper::channel::unbounded;
+use the_finals_eclipper::live::{process_live, LiveOptions};
+use the_finals_eclipper::project_file::{
+    ExportSettings, ProjectClip, ProjectFile, ProjectInput, PROJECT_EXTENSION,
+};
+use the_finals_eclipper::watch::{watch, WatchOptions};
+use the_finals_eclipper::{
+    thread_count, trimmed_path, write_clips, Args, DetectionArgs, VideoProcessor,
+};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Detect events in a recording and write the clips to a montage
+    Process {
+        #[command(flatten)]
+        args: Args,
+        /// Path to output video, defaults to `<input>.eclipper_trimmed.<ext>`
+        #[arg(short, long, value_hint = FilePath)]
+        output: Option<PathBuf>,
+        /// Also save the result as a project file
+        #[arg(long, value_hint = FilePath)]
+        project: Option<PathBuf>,
+        #[arg(long, default_value_t = false)]
+        hw_accel: bool,
+        /// Process a recording that is still being written, finishes once it hasn't grown for
+        /// this many seconds
+        #[arg(long)]
+        follow: Option<f64>,
+    },
+    /// Detect events in a stream pushed to the eclipper, e.g. by OBS, and write the clips once
+    /// it ends
+    Live {
+        /// Address to listen on, e.g. `srt://0.0.0.0:9000`, `udp://0.0.0.0:1234` or
+        /// `rtmp://0.0.0.0:1935/live/eclipper`
+        url: String,
+        /// Path to output video
+        #[arg(short, long, value_hint = FilePath)]
+        output: PathBuf,
+        #[command(flatten)]
+        detection: DetectionArgs,
+        #[arg(long, default_value_t = false)]
+        hw_accel: bool,
+        /// Directory the stream is buffered in, defaults to a temporary directory
+        #[arg(long, value_hint = clap::ValueHint::DirPath)]
+        buffer_dir: Option<PathBuf>,
+        /// Seconds of the stream kept on disk, moments with a clip are always kept
+        #[arg(long, default_value_t = 300.)]
+        buffer_window: f64,
+        /// Seconds per buffer file
+        #[arg(long, default_value_t = 10.)]
+        segment_duration: f64,
+    },
+    /// Process many recordings, given as files, directories or `*` wildcards
+    Batch {
+        #[arg(required = true, value_hint = FilePath)]
+        inputs: Vec<PathBuf>,
+        #[command(flatten)]
+        detection: DetectionArgs,
+        /// Decoding threads per recording
+        #[arg(long, short = 'j', default_value_t = thread_count())]
+        threads: usize,
+        /// Number of recordings processed at the same time
+        #[arg(long, short = 'p', default_value_t = 1)]
+        parallel: usize,
+        #[arg(long, default_value_t = false)]
+        hw_accel: bool,
+        /// Write one montage of all recordings instead of one per recording
+        #[arg(long, value_hint = FilePath, conflicts_with_all = ["output_dir", "no_montage"])]
+        combined: Option<PathBuf>,
+        /// Directory for the per recording montages, defaults to next to each recording
+        #[arg(long, value_hint = clap::ValueHint::DirPath)]
+        output_dir: Option<PathBuf>,
+        /// Only detect events and fill the caches
+        #[arg(long, default_value_t = false)]
+        no_montage: bool,
+        /// Directory for the detection caches, defaults to next to each recording
+        #[arg(long, value_hint = clap::ValueHint::DirPath)]
+        cache_dir: Option<PathBuf>,
+    },
+    /// Watch a directory, e.g. the OBS output folder, and process new recordings once they are
+    /// finished
+    Watch {
+        #[arg(value_hint = clap::ValueHint::DirPath)]
+        dir: PathBuf,
+        /// Directory for the montages, caches and the log of processed recordings
+        #[arg(short, long, value_hint = clap::ValueHint::DirPath)]
+        output_dir: PathBuf,
+        #[command(flatten)]
+        detection: DetectionArgs,
+        #[arg(long, short = 'j', default_value_t = thread_count())]
+        threads: usize,
+        #[arg(long, default_value_t = false)]
+        hw_accel: bool,
+        /// Seconds between checks for new recordings
+        #[arg(long, default_value_t = 5.)]
+        poll_interval: f64,
+        /// Seconds the size of a recording has to stay the same before it is processed
+        #[arg(long, default_value_t = 10.)]
+        settle_time: f64,
+        /// Also process recordings that are already in the directory
+        #[arg(long, default_value_t = false)]
+        include_existing: bool,
+    },
+    /// Write the clips of a project file to a montage
+    Export {
+        /// Path to the `.eclipproj` file
+        #[arg(value_hint = FilePath)]
+        project: PathBuf,
+        /// Path to output video, defaults to the last export path of the project
+        #[arg(short, long, value_hint = FilePath)]
+        output: Option<PathBuf>,
+    },
+}
+
+fn main() {
+    tracing_subscriber::fmt().without_time().init();
+
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Process {
+            args,
+            output,
+            project,
+            hw_accel,
+            follow,
+        } => process(args, output, project, hw_accel, follow),
+        Command::Export { project, output } => export(project, output),
+        Command::Live {
+            url,
+            output,
+            detection,
+            hw_accel,
+            buffer_dir,
+            buffer_window,
+            segment_duration,
+        } => live(
+            Args {
+                input: PathBuf::from(url),
+                detection,
+                // a stream is processed on a single thread as it arrives
+                threads: 1,
+                cache_dir: None,
+            },
+            LiveOptions {
+                buffer_dir: buffer_dir
+                    .unwrap_or_else(|| std::env::temp_dir().join("eclipper_live")),
+                window: buffer_window,
+                segment_duration,
+            },
+            output,
+            hw_accel,
+        ),
+        Command::Batch {
+            inputs,
+            detection,
+            threads,
+            parallel,
+            hw_accel,
+            combined,
+            output_dir,
+            no_montage,
+            cache_dir,
+        } => {
+            let montage = match (combined, no_montage) {
+                (_, true) => Montage::None,
+                (Some(output), false) => Montage::Combined(output),
+                (None, false) => Montage::PerFile { output_dir },
+            };
+            batch(
+                inputs,
+                BatchOptions {
+                    detection,
+                    threads,
+                    parallel,
+                    hw_accel,
+                    montage,
+                    cache_dir,
+                },
+            )
+        }
+        Command::Watch {
+            dir,
+            output_dir,
+            detection,
+            threads,
+            hw_accel,
+            poll_interval,
+            settle_time,
+            include_existing,
+        } => watch(&WatchOptions {
+            dir,
+            output_dir,
+            poll_interval: Duration::from_secs_f64(poll_interval),
+            settle_time: Duration::from_secs_f64(settle_time),
+            include_existing,
+            batch: BatchOptions {
+                detection,
+                threads,
+                parallel: 1,
+                hw_accel,
+                // replaced by the output directory
+                montage: Montage::None,
+                cache_dir: None,
+            },
+        }),
+    };
+
+    if let Err(err) = result {
+        error!("{err}");
+        std::process::exit(1);
+    }
+}
+
+fn process(
+    args: Args,
+    output: Option<PathBuf>,
+    project: Option<PathBuf>,
+    hw_accel: bool,
+    follow: Option<f64>,
+) -> Result<(), String> {
+    let input = args.input.clone();
+    let output = output.unwrap_or_else(|| trimmed_path(&input));
+    let export = ExportSettings {
+        output: Some(output.clone()),
+        include_assists: args.detection.include_assists,
+        include_spectating: args.detection.include_spectating,
+        include_deaths: args.detection.include_deaths,
+        elim_clip_duration: args.detection.elim_clip_duration,
+        pre_roll: args.detection.pre_roll.clone(),
+        post_roll: args.detection.post_roll.clone(),
+        multi_kill_window: args.detection.multi_kill_window,
+        multi_kills_only: args.detection.multi_kills_only,
+    };
+
+    // preview frames are only used by the editor
+    let (progress_tx, progress_rx) = unbounded();
+    let (frame_tx, _) = unbounded();
+    let video_processor = VideoProcessor::open(args, progress_tx, frame_tx);
+    let join_handle = std::thread::spawn(move || match follow {
+        Some(idle_timeout) => {
+            video_processor.process_growing(hw_accel, Duration::from_secs_f64(idle_timeout))
+        }
+        None => video_processor.process(hw_accel),
+    });
+
+    let mut stderr = std::io::stderr();
+    while let Ok(progress) = progress_rx.recv() {
+        write!(
+            stderr,
+            "\rprogress: {:.1}% speed: {:.1}x",
+            progress.progress, progress.speed
+        )
+        .ok();
+    }
+    writeln!(stderr).ok();
+    let result = join_handle
+        .join()
+        .map_err(|_| "processing thread panicked".to_string())?;
+    info!("found {} clip(s)", result.clips.len());
+
+    if let Some(project) = project {
+        let project_file = ProjectFile::new(
+            vec![ProjectInput {
+                path: input.clone(),
+                keyframes: result.keyframes.clone(),
+                duration: Some(result.input_duration),
+                events: result.detections.clone(),
+            }],
+            result
+                .clips
+                .iter()
+                .map(|clip| ProjectClip {
+                    source: 0,
+                    start: clip[0],
+                    end: clip[1],
+                })
+                .collect(),
+            export,
+        );
+        let project = project.with_extension(PROJECT_EXTENSION);
+        project_file
+            .save(&project)
+            .map_err(|err| format!("failed to save project: {err}"))?;
+        info!("project saved to {}", project.display());
+    }
+
+    if result.clips.is_empty() {
+        return Ok(());
+    }
+    write_clips(&input, &output, &result.clips, &result.keyframes);
+    info!("clips written to {}", output.display());
+    Ok(())
+}
+
+fn live(args: Args, options: LiveOptions, output: PathBuf, hw_accel: bool) -> Result<(), String> {
+    let (frame_tx, _) = unbounded();
+    let (result, mut buffer) = process_live(args, options, frame_tx, hw_accel)?;
+    info!("found {} clip(s)", result.clips.len());
+
+    if !result.clips.is_empty() {
+        buffer
+            .write_clips(&result.clips, &output)
+            .map_err(|err| format!("failed to write clips: {err}"))?;
+        info!("clips written to {}", output.display());
+    }
+    buffer.clear();
+    Ok(())
+}
+
+fn batch(inputs: Vec<PathBuf>, options: BatchOptions) -> Result<(), String> {
+    let inputs = expand_inputs(&inputs);
+    if inputs.is_empty() {
+        return Err("no recordings found".to_string());
+    }
+    info!("processing {} recording(s)", inputs.len());
+
+    let (events_tx, events_rx) = unbounded();
+    let count = inputs.len();
+    let printer = std::thread::spawn(move || {
+        let mut stderr = std::io::stderr();
+        while let Ok(event) = events_rx.recv() {
+            match event {
+                BatchEvent::Progress {
+                    index,
+                    progress,
+                    speed,
+                } => {
+                    write!(
+                        stderr,
+                        "\r[{}/{count}] progress: {progress:.1}% speed: {speed:.1}x",
+                        index + 1
+                    )
+                    .ok();
+                }
+                BatchEvent::Finished { index, clips, .. } => {
+                    writeln!(stderr).ok();
+                    info!("[{}/{count}] found {clips} clip(s)", index + 1);
+                }
+                BatchEvent::Started { .. }
+                | BatchEvent::Failed { .. }
+                | BatchEvent::MontageWritten { .. } => {}
+            }
+        }
+    });
+    let items = run_batch(inputs, &options, events_tx);
+    printer.join().ok();
+
+    let failed = items.iter().filter(|item| item.result.is_err()).count();
+    if failed > 0 {
+        return Err(format!("{failed} of {count} recording(s) failed"));
+    }
+    Ok(())
+}
+
+fn export(project: PathBuf, output: Option<PathBuf>) -> Result<(), String> {
+    let project_file = ProjectFile::open(&project)
+        .map_err(|err| format!("failed to open {}: {err}", project.display()))?;
+    let output = output
+        .or(project_file.export.output.clone())
+        .or_else(|| project_file.inputs.first().map(|i| trimmed_path(&i.path)))
+        .ok_or("no output path")?;
+    project_file
+        .export(&output)
+        .map_err(|err| format!("failed to export: {err}"))?;
+    info!("clips written to {}", output.display());
+    Ok(())
+}
@@ -0,0 +1,182 @@
+use serde::Serialize;
+
+use crate::process::{Detection, EventKind};
+
+/// loading screens detected closer together than this belong to the same match
+const MIN_MATCH_LENGTH: f64 = 120.;
+
+/// A match of a recording, from its loading screen to its summary screen
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MatchSegment {
+    /// time in seconds, the start of the recording if the loading screen wasn't recorded
+    pub start: f64,
+    /// time in seconds, the start of the next match or the end of the recording
+    /// if the summary screen wasn't recorded
+    pub end: f64,
+    /// whether the summary screen was detected
+    pub finished: bool,
+}
+
+impl MatchSegment {
+    pub fn contains(&self, time: f64) -> bool {
+        self.start <= time && time < self.end
+    }
+}
+
+/// Splits a recording into matches using the loading and summary screens in `detections`.
+///
+/// Empty if none of them were detected, e.g. for a recording of a single moment
+pub fn segment_matches(detections: &[Detection], input_duration: f64) -> Box<[MatchSegment]> {
+    let mut matches = Vec::<MatchSegment>::new();
+    // whether the last match hasn't reached its summary screen yet
+    let mut open = false;
+
+    for detection in detections {
+        let time = detection.time;
+        match detection.kind {
+            EventKind::MatchStart => {
+                if let Some(last) = matches.last_mut().filter(|_| open) {
+                    if time - last.start < MIN_MATCH_LENGTH {
+                        continue;
+                    }
+                    last.end = time;
+                }
+                matches.push(MatchSegment {
+                    start: time,
+                    end: input_duration,
+                    finished: false,
+                });
+                open = true;
+            }
+            EventKind::Summary => {
+                if !open {
+                    // the summary is shown for a while, it's detected again after the timeout
+                    if matches
+                        .last()
+                        .is_some_and(|last| time - last.end < MIN_MATCH_LENGTH)
+                    {
+                        continue;
+                    }
+                    matches.push(implicit_match(&matches, input_duration));
+                }
+                let last = matches.last_mut().unwrap();
+                last.end = time;
+                last.finished = true;
+                open = false;
+            }
+            _ => {}
+        }
+    }
+    matches.into_boxed_slice()
+}
+
+/// A match whose loading screen wasn't detected, starting where the previous one ended
+fn implicit_match(matches: &[MatchSegment], input_duration: f64) -> MatchSegment {
+    MatchSegment {
+        start: matches.last().map_or(0., |last| last.end),
+        end: input_duration,
+        finished: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detection(time: f64, kind: EventKind) -> Detection {
+        Detection {
+            time,
+            kind,
+            spectating: false,
+            states: Vec::new(),
+            ocr: None,
+            count: 1,
+            rows: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn splits_matches_at_loading_and_summary_screens() {
+        let detections = [
+            detection(10., EventKind::MatchStart),
+            detection(15., EventKind::MatchStart),
+            detection(50., EventKind::Elimination),
+            detection(600., EventKind::Summary),
+            detection(700., EventKind::MatchStart),
+        ];
+        let matches = segment_matches(&detections, 1000.);
+        assert_eq!(
+            *matches,
+            [
+                MatchSegment {
+                    start: 10.,
+                    end: 600.,
+                    finished: true,
+                },
+                MatchSegment {
+                    start: 700.,
+                    end: 1000.,
+                    finished: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn a_new_loading_screen_ends_an_unfinished_match() {
+        let detections = [
+            detection(10., EventKind::MatchStart),
+            detection(400., EventKind::MatchStart),
+        ];
+        let matches = segment_matches(&detections, 1000.);
+        assert_eq!(matches.len(), 2);
+        assert_eq!((matches[0].end, matches[0].finished), (400., false));
+        assert_eq!(matches[1].start, 400.);
+    }
+
+    #[test]
+    fn matches_without_a_loading_screen_start_after_the_previous_one() {
+        let detections = [
+            detection(100., EventKind::Elimination),
+            detection(500., EventKind::Summary),
+            detection(900., EventKind::Summary),
+        ];
+        let matches = segment_matches(&detections, 1000.);
+        assert_eq!(
+            *matches,
+            [
+                MatchSegment {
+                    start: 0.,
+                    end: 500.,
+                    finished: true,
+                },
+                MatchSegment {
+                    start: 500.,
+                    end: 900.,
+                    finished: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_a_summary_detected_again() {
+        let detections = [
+            detection(10., EventKind::MatchStart),
+            detection(500., EventKind::Summary),
+            detection(530., EventKind::Summary),
+        ];
+        let matches = segment_matches(&detections, 1000.);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].end, 500.);
+    }
+
+    #[test]
+    fn empty_without_match_screens() {
+        let detections = [
+            detection(10., EventKind::Elimination),
+            detection(20., EventKind::Win),
+        ];
+        assert!(segment_matches(&detections, 1000.).is_empty());
+    }
+}
@@ -0,0 +1,623 @@
+use std::path::PathBuf;
+
+use ffmpeg_next::ffi::{av_samples_set_silence, AVPixelFormat, AVSampleFormat, AV_TIME_BASE};
+use ffmpeg_next::{codec, decoder, encoder, filter, format, frame, media, Packet, Rational};
+use tracing::warn;
+
+use crate::ffmpeg;
+use crate::video_decoder::t_to_secs;
+
+/// frame rate of the montage if the first recording doesn't report one
+const DEFAULT_FRAME_RATE: i32 = 60;
+
+/// Re-encodes clips into a single video with the resolution, frame rate and audio tracks of
+/// the first recording. Other recordings are scaled and padded to fit, their audio tracks are
+/// matched to the montage's in order and tracks they don't have are silent.
+///
+/// Much slower than [`ClipWriter`](crate::clip_writer::ClipWriter), only used for montages of
+/// recordings that can't be stream copied into one file.
+pub struct MontageEncoder {
+    octx: format::context::Output,
+    video: VideoOutput,
+    /// one per audio stream of the first recording
+    audio: Vec<AudioOutput>,
+}
+
+struct VideoOutput {
+    encoder: encoder::Video,
+    stream_index: usize,
+    width: u32,
+    height: u32,
+    frame_rate: Rational,
+    /// pts of the next frame, in frames
+    next_pts: i64,
+}
+
+struct AudioOutput {
+    encoder: encoder::Audio,
+    stream_index: usize,
+    /// splits the samples of all clips into frames of the size the encoder expects
+    fifo: filter::Graph,
+    /// samples passed to the fifo so far
+    samples: i64,
+}
+
+/// An audio stream of the recording a clip is taken from
+struct ClipAudio {
+    stream_index: usize,
+    time_base: Rational,
+    decoder: decoder::Audio,
+    filter: filter::Graph,
+    /// whether the start of the clip has been padded with silence
+    synced: bool,
+}
+
+impl MontageEncoder {
+    pub fn new(first_input: &PathBuf, output: &PathBuf) -> Result<MontageEncoder, ffmpeg::Error> {
+        ffmpeg::init()?;
+        let ictx = format::input(first_input)?;
+        let mut octx = format::output(output)?;
+        let global_header = octx.format().flags().contains(format::Flags::GLOBAL_HEADER);
+
+        let video = VideoOutput::new(&ictx, &mut octx, output, global_header)?;
+        let audio = ictx
+            .streams()
+            .filter(|ist| ist.parameters().medium() == media::Type::Audio)
+            .map(|ist| AudioOutput::new(&ist, &mut octx, output, global_header))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        octx.set_metadata(ictx.metadata().to_owned());
+        octx.write_header()?;
+
+        Ok(MontageEncoder { octx, video, audio })
+    }
+
+    /// Appends `clips` of `input`, frames are taken from `start` up to but excluding `end`
+    pub fn write_clips(
+        &mut self,
+        input: &PathBuf,
+        clips: &[[f64; 2]],
+    ) -> Result<(), ffmpeg::Error> {
+        let mut ictx = format::input(input)?;
+        for clip in clips {
+            self.write_clip(&mut ictx, clip[0], clip[1])?;
+        }
+        Ok(())
+    }
+
+    fn write_clip(
+        &mut self,
+        ictx: &mut format::context::Input,
+        start: f64,
+        end: f64,
+    ) -> Result<(), ffmpeg::Error> {
+        let video_stream = ictx
+            .streams()
+            .best(media::Type::Video)
+            .ok_or(ffmpeg::Error::StreamNotFound)?;
+        let video_index = video_stream.index();
+        let video_time_base = video_stream.time_base();
+        let mut video_decoder =
+            codec::context::Context::from_parameters(video_stream.parameters())?
+                .decoder()
+                .video()?;
+        let mut video_filter = self.video.filter(&video_decoder, video_time_base)?;
+
+        let audio_streams: Vec<_> = ictx
+            .streams()
+            .filter(|stream| stream.parameters().medium() == media::Type::Audio)
+            .collect();
+        if audio_streams.len() > self.audio.len() {
+            warn!(
+                "only the first {} of {} audio tracks are in the montage",
+                self.audio.len(),
+                audio_streams.len()
+            );
+        }
+        let mut audio = Vec::new();
+        for (output, stream) in self.audio.iter().zip(audio_streams) {
+            let decoder = codec::context::Context::from_parameters(stream.parameters())?
+                .decoder()
+                .audio()?;
+            audio.push(ClipAudio {
+                stream_index: stream.index(),
+                time_base: stream.time_base(),
+                filter: output.filter(&decoder, stream.time_base())?,
+                decoder,
+                synced: false,
+            });
+        }
+
+        // where the clip starts in the output, audio is padded with silence up to it
+        let offset = self.video.next_pts as f64 / f64::from(self.video.frame_rate);
+
+        let start_ts = (start * AV_TIME_BASE as f64) as i64;
+        ictx.seek(start_ts, ..start_ts)?;
+
+        for (stream, packet) in ictx.packets() {
+            let time_base = stream.time_base();
+            if stream.index() == video_index {
+                video_decoder.send_packet(&packet).ok();
+                self.video.receive_frames(
+                    &mut video_decoder,
+                    &mut video_filter,
+                    &mut self.octx,
+                    video_time_base,
+                    [start, end],
+                    offset,
+                )?;
+            } else if let Some(track) = audio
+                .iter()
+                .position(|audio| audio.stream_index == stream.index())
+            {
+                let clip_audio = &mut audio[track];
+                clip_audio.decoder.send_packet(&packet).ok();
+                self.audio[track].receive_frames(
+                    clip_audio,
+                    &mut self.octx,
+                    [start, end],
+                    offset,
+                )?;
+            }
+            // packets are not strictly ordered, read a bit past the end of the clip
+            if t_to_secs(packet.pts().unwrap_or(0), time_base) > end + 1. {
+                break;
+            }
+        }
+
+        video_decoder.send_eof().ok();
+        self.video.receive_frames(
+            &mut video_decoder,
+            &mut video_filter,
+            &mut self.octx,
+            video_time_base,
+            [start, end],
+            offset,
+        )?;
+        for (output, mut clip_audio) in self.audio.iter_mut().zip(audio) {
+            clip_audio.decoder.send_eof().ok();
+            output.receive_frames(&mut clip_audio, &mut self.octx, [start, end], offset)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the encoders and finishes the file
+    pub fn finish(mut self) -> Result<(), ffmpeg::Error> {
+        self.video.encoder.send_eof()?;
+        self.video.write_packets(&mut self.octx)?;
+        let duration = self.video.next_pts as f64 / f64::from(self.video.frame_rate);
+        for audio in &mut self.audio {
+            // tracks the last recordings didn't have are silent up to the end of the video
+            let missing = (duration * audio.encoder.rate() as f64).round() as i64 - audio.samples;
+            if missing > 0 {
+                audio.add_silence(missing as usize, &mut self.octx)?;
+            }
+            audio.fifo.get("in").unwrap().source().flush()?;
+            audio.encode_fifo(&mut self.octx)?;
+            audio.encoder.send_eof()?;
+            audio.write_packets(&mut self.octx)?;
+        }
+        self.octx.write_trailer()
+    }
+}
+
+impl VideoOutput {
+    fn new(
+        ictx: &format::context::Input,
+        octx: &mut format::context::Output,
+        output: &PathBuf,
+        global_header: bool,
+    ) -> Result<VideoOutput, ffmpeg::Error> {
+        let ist = ictx
+            .streams()
+            .best(media::Type::Video)
+            .ok_or(ffmpeg::Error::StreamNotFound)?;
+        let decoder = codec::context::Context::from_parameters(ist.parameters())?
+            .decoder()
+            .video()?;
+        let frame_rate = match ist.avg_frame_rate() {
+            rate if rate.numerator() > 0 && rate.denominator() > 0 => rate,
+            _ => Rational::new(DEFAULT_FRAME_RATE, 1),
+        };
+
+        let codec = encoder::find(codec::Id::H264)
+            .or_else(|| encoder::find(octx.format().codec(output, media::Type::Video)))
+            .ok_or(ffmpeg::Error::EncoderNotFound)?;
+        let mut ost = octx.add_stream(codec)?;
+        let mut encoder = codec::context::Context::new_with_codec(codec)
+            .encoder()
+            .video()?;
+        encoder.set_width(decoder.width());
+        encoder.set_height(decoder.height());
+        encoder.set_format(format::Pixel::YUV420P);
+        encoder.set_frame_rate(Some(frame_rate));
+        encoder.set_time_base(frame_rate.invert());
+        if global_header {
+            encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        let encoder = encoder.open_as(codec)?;
+        ost.set_parameters(&encoder);
+        ost.set_time_base(frame_rate.invert());
+
+        Ok(VideoOutput {
+            stream_index: ost.index(),
+            width: decoder.width(),
+            height: decoder.height(),
+            encoder,
+            frame_rate,
+            next_pts: 0,
+        })
+    }
+
+    /// scales the frames of a recording to the montage size, keeping the aspect ratio
+    fn filter(
+        &self,
+        decoder: &decoder::Video,
+        time_base: Rational,
+    ) -> Result<filter::Graph, ffmpeg::Error> {
+        let mut graph = filter::Graph::new();
+        let args = format!(
+            "video_size={}x{}:pix_fmt={}:time_base={}:pixel_aspect=1/1",
+            decoder.width(),
+            decoder.height(),
+            AVPixelFormat::from(decoder.format()) as i32,
+            time_base,
+        );
+        graph.add(&filter::find("buffer").unwrap(), "in", &args)?;
+        graph.add(&filter::find("buffersink").unwrap(), "out", "")?;
+        let (w, h) = (self.width, self.height);
+        let spec = format!(
+            "scale={w}:{h}:force_original_aspect_ratio=decrease,\
+             pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1,format=yuv420p"
+        );
+        graph.output("in", 0)?.input("out", 0)?.parse(&spec)?;
+        graph.validate()?;
+        Ok(graph)
+    }
+
+    fn receive_frames(
+        &mut self,
+        decoder: &mut decoder::Video,
+        filter: &mut filter::Graph,
+        octx: &mut format::context::Output,
+        time_base: Rational,
+        [start, end]: [f64; 2],
+        offset: f64,
+    ) -> Result<(), ffmpeg::Error> {
+        let mut decoded = frame::Video::empty();
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            let Some(ts) = decoded.timestamp() else {
+                continue;
+            };
+            let t = t_to_secs(ts, time_base);
+            if t < start || t >= end {
+                continue;
+            }
+            decoded.set_pts(Some(ts));
+            filter.get("in").unwrap().source().add(&decoded)?;
+
+            let mut filtered = frame::Video::empty();
+            while filter
+                .get("out")
+                .unwrap()
+                .sink()
+                .frame(&mut filtered)
+                .is_ok()
+            {
+                let t = t_to_secs(filtered.pts().unwrap_or(ts), time_base);
+                // gaps in variable frame rate recordings are kept, pts have to increase though
+                let pts = ((offset + t - start) * f64::from(self.frame_rate)).round() as i64;
+                let pts = pts.max(self.next_pts);
+                filtered.set_pts(Some(pts));
+                filtered.set_kind(ffmpeg::picture::Type::None);
+                self.next_pts = pts + 1;
+                self.encoder.send_frame(&filtered)?;
+                self.write_packets(octx)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_packets(&mut self, octx: &mut format::context::Output) -> Result<(), ffmpeg::Error> {
+        let ost_time_base = octx.stream(self.stream_index).unwrap().time_base();
+        let mut encoded = Packet::empty();
+        while self.encoder.receive_packet(&mut encoded).is_ok() {
+            encoded.set_stream(self.stream_index);
+            encoded.rescale_ts(self.frame_rate.invert(), ost_time_base);
+            encoded.write_interleaved(octx)?;
+        }
+        Ok(())
+    }
+}
+
+impl AudioOutput {
+    fn new(
+        ist: &format::stream::Stream,
+        octx: &mut format::context::Output,
+        output: &PathBuf,
+        global_header: bool,
+    ) -> Result<AudioOutput, ffmpeg::Error> {
+        let decoder = codec::context::Context::from_parameters(ist.parameters())?
+            .decoder()
+            .audio()?;
+        let codec = encoder::find(octx.format().codec(output, media::Type::Audio))
+            .ok_or(ffmpeg::Error::EncoderNotFound)?
+            .audio()?;
+
+        let mut ost = octx.add_stream(codec)?;
+        let mut encoder = codec::context::Context::from_parameters(ost.parameters())?
+            .encoder()
+            .audio()?;
+        let channel_layout = codec
+            .channel_layouts()
+            .map(|layouts| layouts.best(decoder.channel_layout().channels()))
+            .unwrap_or(ffmpeg::ChannelLayout::STEREO);
+        encoder.set_rate(decoder.rate() as i32);
+        encoder.set_channel_layout(channel_layout);
+        encoder.set_format(
+            codec
+                .formats()
+                .and_then(|mut formats| formats.next())
+                .unwrap_or(decoder.format()),
+        );
+        encoder.set_time_base((1, decoder.rate() as i32));
+        if global_header {
+            encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        let encoder = encoder.open_as(codec)?;
+        ost.set_parameters(&encoder);
+        ost.set_time_base((1, decoder.rate() as i32));
+
+        let mut fifo = filter::Graph::new();
+        let args = format!(
+            "time_base=1/{rate}:sample_rate={rate}:sample_fmt={}:channel_layout=0x{:x}",
+            encoder.format().name(),
+            encoder.channel_layout().bits(),
+            rate = encoder.rate(),
+        );
+        fifo.add(&filter::find("abuffer").unwrap(), "in", &args)?;
+        fifo.add(&filter::find("abuffersink").unwrap(), "out", "")?;
+        fifo.output("in", 0)?.input("out", 0)?.parse("anull")?;
+        fifo.validate()?;
+        if !codec
+            .capabilities()
+            .contains(codec::capabilities::Capabilities::VARIABLE_FRAME_SIZE)
+        {
+            fifo.get("out")
+                .unwrap()
+                .sink()
+                .set_frame_size(encoder.frame_size());
+        }
+
+        Ok(AudioOutput {
+            stream_index: ost.index(),
+            encoder,
+            fifo,
+            samples: 0,
+        })
+    }
+
+    /// converts the samples of a recording to the format of the encoder
+    fn filter(
+        &self,
+        decoder: &decoder::Audio,
+        time_base: Rational,
+    ) -> Result<filter::Graph, ffmpeg::Error> {
+        let mut graph = filter::Graph::new();
+        let args = format!(
+            "time_base={}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+            time_base,
+            decoder.rate(),
+            decoder.format().name(),
+            decoder.channel_layout().bits()
+        );
+        graph.add(&filter::find("abuffer").unwrap(), "in", &args)?;
+        graph.add(&filter::find("abuffersink").unwrap(), "out", "")?;
+        {
+            let mut out = graph.get("out").unwrap();
+            out.set_sample_format(self.encoder.format());
+            out.set_channel_layout(self.encoder.channel_layout());
+            out.set_sample_rate(self.encoder.rate());
+        }
+        graph.output("in", 0)?.input("out", 0)?.parse("anull")?;
+        graph.validate()?;
+        Ok(graph)
+    }
+
+    fn receive_frames(
+        &mut self,
+        clip_audio: &mut ClipAudio,
+        octx: &mut format::context::Output,
+        [start, end]: [f64; 2],
+        offset: f64,
+    ) -> Result<(), ffmpeg::Error> {
+        let ClipAudio {
+            time_base,
+            decoder,
+            filter,
+            synced,
+            ..
+        } = clip_audio;
+        let time_base = *time_base;
+        let rate = i64::from(self.encoder.rate());
+        let mut decoded = frame::Audio::empty();
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            let Some(ts) = decoded.timestamp() else {
+                continue;
+            };
+            let t = t_to_secs(ts, time_base);
+            if t < start || t >= end {
+                continue;
+            }
+            decoded.set_pts(Some(ts));
+            filter.get("in").unwrap().source().add(&decoded)?;
+
+            let mut converted = frame::Audio::empty();
+            while filter
+                .get("out")
+                .unwrap()
+                .sink()
+                .frame(&mut converted)
+                .is_ok()
+            {
+                let expected = ((offset + t - start) * rate as f64).round() as i64;
+                if !*synced {
+                    // the previous clip had less audio than video, or this one starts late
+                    if expected > self.samples {
+                        self.add_silence((expected - self.samples) as usize, octx)?;
+                    }
+                    *synced = true;
+                }
+                // drop frames until the audio catches up with the video instead of drifting
+                if self.samples - expected >= converted.samples() as i64 {
+                    continue;
+                }
+                self.add_to_fifo(&mut converted, octx)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_silence(
+        &mut self,
+        samples: usize,
+        octx: &mut format::context::Output,
+    ) -> Result<(), ffmpeg::Error> {
+        let mut silence = frame::Audio::new(
+            self.encoder.format(),
+            samples,
+            self.encoder.channel_layout(),
+        );
+        silence.set_rate(self.encoder.rate());
+        unsafe {
+            av_samples_set_silence(
+                (*silence.as_mut_ptr()).extended_data,
+                0,
+                samples as _,
+                self.encoder.channel_layout().channels(),
+                AVSampleFormat::from(self.encoder.format()),
+            );
+        }
+        self.add_to_fifo(&mut silence, octx)
+    }
+
+    fn add_to_fifo(
+        &mut self,
+        frame: &mut frame::Audio,
+        octx: &mut format::context::Output,
+    ) -> Result<(), ffmpeg::Error> {
+        frame.set_pts(Some(self.samples));
+        self.samples += frame.samples() as i64;
+        self.fifo.get("in").unwrap().source().add(frame)?;
+        self.encode_fifo(octx)
+    }
+
+    fn encode_fifo(&mut self, octx: &mut format::context::Output) -> Result<(), ffmpeg::Error> {
+        let mut chunk = frame::Audio::empty();
+        while self
+            .fifo
+            .get("out")
+            .unwrap()
+            .sink()
+            .frame(&mut chunk)
+            .is_ok()
+        {
+            self.encoder.send_frame(&chunk)?;
+            self.write_packets(octx)?;
+        }
+        Ok(())
+    }
+
+    fn write_packets(&mut self, octx: &mut format::context::Output) -> Result<(), ffmpeg::Error> {
+        let ost_time_base = octx.stream(self.stream_index).unwrap().time_base();
+        let encoder_time_base = Rational::new(1, self.encoder.rate() as i32);
+        let mut encoded = Packet::empty();
+        while self.encoder.receive_packet(&mut encoded).is_ok() {
+            encoded.set_stream(self.stream_index);
+            encoded.rescale_ts(encoder_time_base, ost_time_base);
+            encoded.write_interleaved(octx)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_video::{frame_luma, temp_path, write_video};
+    use crate::VideoDecoder;
+
+    /// decoded samples of every audio stream of `path`
+    fn audio_samples(path: &PathBuf) -> Vec<usize> {
+        let mut ictx = format::input(path).unwrap();
+        let mut decoders: Vec<(usize, decoder::Audio, usize)> = ictx
+            .streams()
+            .filter(|stream| stream.parameters().medium() == media::Type::Audio)
+            .map(|stream| {
+                let decoder = codec::context::Context::from_parameters(stream.parameters())
+                    .unwrap()
+                    .decoder()
+                    .audio()
+                    .unwrap();
+                (stream.index(), decoder, 0)
+            })
+            .collect();
+        let mut decoded = frame::Audio::empty();
+        for (stream, packet) in ictx.packets() {
+            let Some((_, decoder, samples)) = decoders
+                .iter_mut()
+                .find(|(index, ..)| *index == stream.index())
+            else {
+                continue;
+            };
+            decoder.send_packet(&packet).unwrap();
+            while decoder.receive_frame(&mut decoded).is_ok() {
+                *samples += decoded.samples();
+            }
+        }
+        decoders
+            .into_iter()
+            .map(|(_, mut decoder, mut samples)| {
+                decoder.send_eof().unwrap();
+                while decoder.receive_frame(&mut decoded).is_ok() {
+                    samples += decoded.samples();
+                }
+                samples
+            })
+            .collect()
+    }
+
+    #[test]
+    fn encodes_every_audio_track() {
+        let first = temp_path("encoder_first.mp4");
+        let second = temp_path("encoder_second.mp4");
+        let output = temp_path("encoder_output.mp4");
+        write_video(&first, 100, |_| 40, 2).unwrap();
+        // a recording with fewer audio tracks, its missing track is silent in the montage
+        write_video(&second, 50, |_| 200, 1).unwrap();
+
+        let mut encoder = MontageEncoder::new(&first, &output).unwrap();
+        encoder.write_clips(&first, &[[1., 3.]]).unwrap();
+        encoder.write_clips(&second, &[[0., 2.]]).unwrap();
+        encoder.finish().unwrap();
+
+        let mut decoder = VideoDecoder::open(&output, false).unwrap();
+        let lumas: Vec<u8> = decoder
+            .decode_iter()
+            .map(|frame| frame_luma(&frame))
+            .collect();
+        assert_eq!(lumas.len(), 40);
+        assert_eq!(lumas.iter().take_while(|luma| **luma < 128).count(), 20);
+
+        let samples = audio_samples(&output);
+        assert_eq!(samples.len(), 2);
+        for samples in samples {
+            // four seconds, give or take the frames the encoder pads
+            assert!(samples.abs_diff(4 * 48000) <= 4096, "{samples} samples");
+        }
+
+        for path in [first, second, output] {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use serde::Serialize;
+
+use crate::process::{Detection, EventKind};
+
+/// players per team, the kill feed doesn't name teams so eliminating this many in one streak
+/// is counted as a team wipe
+pub const TEAM_SIZE: usize = 3;
+/// detections of an elimination further apart than this are separate kill feed entries,
+/// shorter gaps are frames tesseract didn't read
+const KILL_FEED_GAP: f64 = 1.;
+/// kill feed entries whose rows are closer than this fraction of the search area are the same
+const SAME_ROW: f32 = 0.15;
+
+/// Eliminations that followed each other within the multi-kill window
+#[derive(Debug, Clone, Serialize)]
+pub struct KillStreak {
+    /// time of the first elimination in seconds
+    pub start: f64,
+    /// time of the last elimination in seconds
+    pub end: f64,
+    pub kills: usize,
+}
+
+impl KillStreak {
+    pub fn is_multi_kill(&self) -> bool {
+        self.kills >= 2
+    }
+
+    pub fn is_team_wipe(&self) -> bool {
+        self.kills >= TEAM_SIZE
+    }
+}
+
+/// The largest streak of a clip, the tag of the clip in the process result
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClipStreak {
+    /// 0 for clips without eliminations
+    pub kills: usize,
+    pub team_wipe: bool,
+}
+
+/// Times of your own eliminations. An entry stays in the kill feed for a while and is detected on
+/// every searched frame, so a kill is counted when an entry is read in a row where none was
+/// shortly before. Detections without rows, like template matches, count a kill when more entries
+/// are read than on the frame before
+fn kill_times(detections: &[Detection]) -> Vec<f64> {
+    let mut kills = Vec::new();
+    let mut previous: Option<(f64, u32)> = None;
+    // rows of the entries in the kill feed and when they were last read
+    let mut entries: Vec<(f32, f64)> = Vec::new();
+    for detection in detections
+        .iter()
+        .filter(|d| d.kind == EventKind::Elimination && !d.spectating)
+    {
+        let time = detection.time;
+        let count = detection.count.max(1);
+        entries.retain(|(_, last)| time - last <= KILL_FEED_GAP);
+        if detection.rows.is_empty() {
+            let visible = match previous {
+                Some((previous, count)) if time - previous <= KILL_FEED_GAP => count,
+                _ => 0,
+            };
+            for _ in visible..count {
+                kills.push(time);
+            }
+        }
+        for row in &detection.rows {
+            match entries
+                .iter_mut()
+                .find(|(entry, _)| (entry - row).abs() < SAME_ROW)
+            {
+                Some(entry) => *entry = (*row, time),
+                None => {
+                    kills.push(time);
+                    entries.push((*row, time));
+                }
+            }
+        }
+        previous = Some((time, count));
+    }
+    kills
+}
+
+/// Groups the eliminations in `detections` into streaks, a kill less than `window` seconds
+/// after the previous one continues its streak
+pub fn kill_streaks(detections: &[Detection], window: f64) -> Vec<KillStreak> {
+    let mut streaks = Vec::<KillStreak>::new();
+    for time in kill_times(detections) {
+        match streaks.last_mut() {
+            Some(streak) if time - streak.end <= window => {
+                streak.end = time;
+                streak.kills += 1;
+            }
+            _ => streaks.push(KillStreak {
+                start: time,
+                end: time,
+                kills: 1,
+            }),
+        }
+    }
+    streaks
+}
+
+/// The largest streak in each clip
+pub fn clip_streaks(
+    clips: &[[f64; 2]],
+    detections: &[Detection],
+    window: f64,
+) -> Box<[ClipStreak]> {
+    let streaks = kill_streaks(detections, window);
+    clips
+        .iter()
+        .map(|clip| {
+            streaks
+                .iter()
+                .filter(|streak| streak.start <= clip[1] && streak.end >= clip[0])
+                .max_by_key(|streak| streak.kills)
+                .map(|streak| ClipStreak {
+                    kills: streak.kills,
+                    team_wipe: streak.is_team_wipe(),
+                })
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detection(time: f64, kind: EventKind, count: u32) -> Detection {
+        Detection {
+            time,
+            kind,
+            spectating: false,
+            states: Vec::new(),
+            ocr: None,
+            count,
+            rows: Vec::new(),
+        }
+    }
+
+    fn elimination(time: f64, count: u32) -> Detection {
+        detection(time, EventKind::Elimination, count)
+    }
+
+    /// an elimination read by ocr with kill feed entries in `rows`
+    fn entries(time: f64, rows: &[f32]) -> Detection {
+        let mut detection = elimination(time, rows.len() as u32);
+        detection.rows = rows.to_vec();
+        detection
+    }
+
+    #[test]
+    fn kill_times_counts_new_kill_feed_entries() {
+        // the first entry is read on every frame until a second one appears below it
+        let detections = [
+            elimination(10., 1),
+            elimination(10.1, 1),
+            elimination(10.2, 1),
+            elimination(11., 2),
+            elimination(11.1, 2),
+        ];
+        assert_eq!(kill_times(&detections), vec![10., 11.]);
+    }
+
+    #[test]
+    fn kill_times_counts_entries_appearing_together() {
+        let detections = [elimination(5., 2), elimination(5.1, 2)];
+        assert_eq!(kill_times(&detections), vec![5., 5.]);
+    }
+
+    #[test]
+    fn kill_times_counts_an_entry_replacing_an_old_one() {
+        // the old entry leaves the kill feed on the frame the new one appears in another row
+        let detections = [
+            entries(10., &[0.2]),
+            entries(10.1, &[0.2]),
+            entries(10.2, &[0.6]),
+            entries(10.3, &[0.6]),
+        ];
+        assert_eq!(kill_times(&detections), vec![10., 10.2]);
+    }
+
+    #[test]
+    fn kill_times_tracks_entries_by_row() {
+        let detections = [
+            entries(5., &[0.2]),
+            entries(5.1, &[0.2, 0.6]),
+            // the second entry isn't read on one frame
+            entries(5.2, &[0.21]),
+            entries(5.3, &[0.2, 0.59]),
+            // both entries are gone, the row of the first one is used again
+            entries(8., &[0.2]),
+        ];
+        assert_eq!(kill_times(&detections), vec![5., 5.1, 8.]);
+    }
+
+    #[test]
+    fn kill_times_starts_over_after_a_gap() {
+        let detections = [elimination(5., 1), elimination(7., 1), elimination(7.1, 1)];
+        assert_eq!(kill_times(&detections), vec![5., 7.]);
+    }
+
+    #[test]
+    fn kill_times_ignores_spectated_and_other_events() {
+        let mut spectated = elimination(20., 1);
+        spectated.spectating = true;
+        let detections = [
+            detection(1., EventKind::Assist, 1),
+            detection(2., EventKind::Win, 1),
+            spectated,
+        ];
+        assert!(kill_times(&detections).is_empty());
+    }
+
+    #[test]
+    fn kill_streaks_groups_kills_within_the_window() {
+        let detections = [
+            elimination(10., 1),
+            elimination(12., 1),
+            elimination(16.5, 1),
+            elimination(30., 1),
+        ];
+        let streaks = kill_streaks(&detections, 5.);
+        assert_eq!(streaks.len(), 2);
+        assert_eq!(
+            (streaks[0].start, streaks[0].end, streaks[0].kills),
+            (10., 16.5, 3)
+        );
+        assert!(streaks[0].is_team_wipe());
+        assert_eq!(
+            (streaks[1].start, streaks[1].end, streaks[1].kills),
+            (30., 30., 1)
+        );
+        assert!(!streaks[1].is_multi_kill());
+    }
+
+    #[test]
+    fn kill_streaks_counts_template_detections_per_entry() {
+        // templates have no ocr, the number of matched icons is counted
+        let detections = [elimination(3., 1), elimination(3.5, 3)];
+        let streaks = kill_streaks(&detections, 5.);
+        assert_eq!(streaks.len(), 1);
+        assert_eq!(streaks[0].kills, 3);
+    }
+
+    #[test]
+    fn clip_streaks_tags_the_largest_streak() {
+        let detections = [
+            elimination(10., 1),
+            elimination(11., 2),
+            elimination(11.5, 3),
+            elimination(40., 1),
+        ];
+        let clips = [[5., 12.], [35., 41.], [60., 70.]];
+        let streaks = clip_streaks(&clips, &detections, 5.);
+        assert_eq!(
+            *streaks,
+            [
+                ClipStreak {
+                    kills: 3,
+                    team_wipe: true,
+                },
+                ClipStreak {
+                    kills: 1,
+                    team_wipe: false,
+                },
+                ClipStreak::default(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use std::path::PathBuf;
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use tesseract::{OcrEngineMode, PageSegMode, Tesseract};
+
+/// Tesseract settings shared by every instance, numbered like the tesseract cli options
+#[derive(clap::Args, Debug, Clone)]
+pub struct TesseractOptions {
+    /// Directory with the `.traineddata` files, defaults to `TESSDATA_PREFIX` or the system location
+    #[arg(long, value_hint = clap::ValueHint::DirPath)]
+    pub tessdata: Option<PathBuf>,
+    /// Page segmentation mode, see `tesseract --help-psm`
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=13))]
+    pub psm: Option<u8>,
+    /// OCR engine mode, see `tesseract --help-oem`
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=3))]
+    pub oem: Option<u8>,
+    /// Keywords tesseract is less confident about (0 to 100) are ignored
+    #[arg(long, default_value_t = DEFAULT_MIN_CONFIDENCE)]
+    pub min_confidence: f32,
+}
+
+/// only rejects words tesseract mostly guessed
+const DEFAULT_MIN_CONFIDENCE: f32 = 20.;
+
+impl Default for TesseractOptions {
+    fn default() -> Self {
+        TesseractOptions {
+            tessdata: None,
+            psm: None,
+            oem: None,
+            min_confidence: DEFAULT_MIN_CONFIDENCE,
+        }
+    }
+}
+
+impl TesseractOptions {
+    /// Creates a tesseract instance for `language` with these options
+    pub fn build(&self, language: &str) -> Result<Tesseract, tesseract::InitializeError> {
+        let tessdata = self
+            .tessdata
+            .as_ref()
+            .map(|dir| dir.to_string_lossy().into_owned());
+        let mut tess = match self.oem {
+            Some(oem) => {
+                Tesseract::new_with_oem(tessdata.as_deref(), Some(language), engine_mode(oem))?
+            }
+            None => Tesseract::new(tessdata.as_deref(), Some(language))?,
+        };
+        if let Some(psm) = self.psm {
+            tess.set_page_seg_mode(page_seg_mode(psm));
+        }
+        Ok(tess)
+    }
+}
+
+/// Restricts recognition to the characters in `whitelist`, `None` allows all characters again
+pub fn set_whitelist(
+    tess: Tesseract,
+    whitelist: Option<&str>,
+) -> Result<Tesseract, tesseract::SetVariableError> {
+    tess.set_variable("tessedit_char_whitelist", whitelist.unwrap_or(""))
+}
+
+fn page_seg_mode(psm: u8) -> PageSegMode {
+    match psm {
+        0 => PageSegMode::PsmOsdOnly,
+        1 => PageSegMode::PsmAutoOsd,
+        2 => PageSegMode::PsmAutoOnly,
+        3 => PageSegMode::PsmAuto,
+        4 => PageSegMode::PsmSingleColumn,
+        5 => PageSegMode::PsmSingleBlockVertText,
+        6 => PageSegMode::PsmSingleBlock,
+        7 => PageSegMode::PsmSingleLine,
+        8 => PageSegMode::PsmSingleWord,
+        9 => PageSegMode::PsmCircleWord,
+        10 => PageSegMode::PsmSingleChar,
+        11 => PageSegMode::PsmSparseText,
+        12 => PageSegMode::PsmSparseTextOsd,
+        _ => PageSegMode::PsmRawLine,
+    }
+}
+
+fn engine_mode(oem: u8) -> OcrEngineMode {
+    match oem {
+        0 => OcrEngineMode::TesseractOnly,
+        1 => OcrEngineMode::LstmOnly,
+        2 => OcrEngineMode::TesseractLstmCombined,
+        _ => OcrEngineMode::Default,
+    }
+}
+
+/// A word recognized by tesseract, read from its tsv output
+#[derive(Debug, Clone)]
+pub struct Word {
+    /// block, paragraph and line number, words of a line are next to each other
+    pub line: (u32, u32, u32),
+    pub text: String,
+    /// 0 to 100
+    pub confidence: f32,
+    /// left, top, width and height in pixels of the image given to tesseract
+    pub bounds: [i32; 4],
+}
+
+impl Word {
+    /// One word of the text of `words` put together with `separator`, as confident as its least
+    /// confident word
+    pub fn join(words: &[Word], separator: &str) -> Word {
+        let left = words.iter().map(|w| w.bounds[0]).min().unwrap_or_default();
+        let top = words.iter().map(|w| w.bounds[1]).min().unwrap_or_default();
+        let right = words
+            .iter()
+            .map(|w| w.bounds[0] + w.bounds[2])
+            .max()
+            .unwrap_or_default();
+        let bottom = words
+            .iter()
+            .map(|w| w.bounds[1] + w.bounds[3])
+            .max()
+            .unwrap_or_default();
+        Word {
+            line: words.first().map(|w| w.line).unwrap_or_default(),
+            text: words
+                .iter()
+                .map(|w| w.text.as_str())
+                .collect::<Vec<_>>()
+                .join(separator),
+            confidence: words
+                .iter()
+                .map(|w| w.confidence)
+                .min_by(f32::total_cmp)
+                .unwrap_or_default(),
+            bounds: [left, top, right - left, bottom - top],
+        }
+    }
+
+    /// `area` is the rectangle tesseract was restricted to, in the same pixels as the bounds
+    pub fn to_match(&self, area: [i32; 4]) -> OcrMatch {
+        let x = |v: i32| ((v - area[0]) as f32 / area[2].max(1) as f32).clamp(0., 1.);
+        let y = |v: i32| ((v - area[1]) as f32 / area[3].max(1) as f32).clamp(0., 1.);
+        let left = x(self.bounds[0]);
+        let top = y(self.bounds[1]);
+        OcrMatch {
+            text: self.text.clone(),
+            confidence: self.confidence,
+            bounds: [
+                left,
+                top,
+                x(self.bounds[0] + self.bounds[2]) - left,
+                y(self.bounds[1] + self.bounds[3]) - top,
+            ],
+        }
+    }
+}
+
+/// Where a keyword was read, kept with the detection to find the cause of false positives
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize, Deserialize)]
+pub struct OcrMatch {
+    /// the word containing the keyword, or the line for patterns of several words
+    pub text: String,
+    /// 0 to 100
+    pub confidence: f32,
+    /// left, top, width and height as fractions of the search area
+    pub bounds: [f32; 4],
+}
+
+/// The words of the last recognized image
+pub fn words(
+    tess: &mut Tesseract,
+) -> Result<Vec<Word>, tesseract::plumbing::TessBaseApiGetTsvTextError> {
+    let tsv = tess.get_tsv_text(0)?;
+    Ok(tsv.lines().filter_map(parse_word).collect())
+}
+
+fn parse_word(line: &str) -> Option<Word> {
+    let columns: Vec<&str> = line.split('\t').collect();
+    // level 5 are words, the other levels are pages, blocks, paragraphs and lines
+    if columns.len() < 12 || columns[0] != "5" {
+        return None;
+    }
+    let text = columns[11].trim();
+    if text.is_empty() {
+        return None;
+    }
+    let number = |index: usize| columns[index].parse::<i32>().ok();
+    Some(Word {
+        line: (number(2)? as u32, number(3)? as u32, number(4)? as u32),
+        text: text.to_string(),
+        confidence: columns[10].parse().ok()?,
+        bounds: [number(6)?, number(7)?, number(8)?, number(9)?],
+    })
+}
@@ -1,10 +1,12 @@
 #[allow(unused_imports)]
 use std::io::Write;
-use std::path::PathBuf;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use clap::Parser;
+use bincode::{Decode, Encode};
 use clap::ValueHint::FilePath;
+use clap::{Parser, ValueEnum};
 use crossbeam_channel::TryRecvError;
 use fast_image_resize as fr;
 use ffmpeg::frame::Video;
@@ -14,11 +16,21 @@ use ffmpeg_next::ffi::{av_hwframe_transfer_data, av_image_copy_to_buffer, AVPixe
 use ffmpeg_next::format::Pixel;
 use serde::{Deserialize, Serialize};
 use tesseract::Tesseract;
-use tracing::{error, info};
-
-use crate::cache_clips::{cache_clips, clips_from_cache, CacheKey};
-use crate::clip_writer::ClipWriter;
+use tracing::{error, info, warn};
+
+use crate::cache_clips::{cache_detections, detections_from_cache};
+use crate::clip_writer::{can_stream_copy, ClipWriter};
+use crate::live::RollingBuffer;
+use crate::match_segment::{segment_matches, MatchSegment};
+use crate::montage_encoder::MontageEncoder;
+use crate::multi_kill::{clip_streaks, kill_streaks, ClipStreak};
+use crate::ocr::{self, set_whitelist, OcrMatch, TesseractOptions, Word};
 use crate::process_frame::{frame_binarisation, frame_brightness_contrast, scale_frame};
+use crate::rule_pack::{
+    find_all, HUD_STATES, LETTERS, SENTENCE_LETTERS, STATE_EVENTS, TEMPLATE_PARAMS,
+    TESSERACT_LANGUAGE,
+};
+use crate::template::Templates;
 use crate::video_decoder::{t_to_secs, VideoDecoder};
 
 /// Commandline args
@@ -33,34 +45,118 @@ pub struct Args {
     // output: PathBuf,
     // #[arg(long, default_value_t = 4)]
     // clip_length: i32,
+    #[command(flatten)]
+    pub detection: DetectionArgs,
+    #[arg(long, short = 'j', default_value_t = super::thread_count())]
+    pub threads: usize,
+    /// Directory for the detection cache, defaults to next to the input
+    #[arg(long, value_hint = clap::ValueHint::DirPath)]
+    pub cache_dir: Option<PathBuf>,
+}
+
+/// What is detected and how the clips around the events are cut, shared by all subcommands
+#[derive(clap::Args, Debug, Clone)]
+pub struct DetectionArgs {
     #[arg(long, default_value_t = false)]
     pub include_assists: bool,
     #[arg(long, default_value_t = false)]
     pub include_spectating: bool,
+    /// Also clip your own eliminations, detected when the spectating bar appears
+    #[arg(long, default_value_t = false)]
+    pub include_deaths: bool,
     #[arg(long, default_value_t = 4.)]
     pub elim_clip_duration: f64,
-    #[arg(long, short = 'j', default_value_t = super::thread_count())]
-    pub threads: usize,
+    /// Seconds of the clip before events of a kind, e.g. `--pre-roll win=15`, can be repeated.
+    /// Takes precedence over `--elim-clip-duration`
+    #[arg(long, value_parser = parse_event_seconds)]
+    pub pre_roll: Vec<(EventKind, f64)>,
+    /// Seconds of the clip after events of a kind, e.g. `--post-roll assist=1`, can be repeated
+    #[arg(long, value_parser = parse_event_seconds)]
+    pub post_roll: Vec<(EventKind, f64)>,
+    /// Seconds between eliminations that still count as one multi-kill
+    #[arg(long, default_value_t = DEFAULT_MULTI_KILL_WINDOW)]
+    pub multi_kill_window: f64,
+    /// Leave out single eliminations, only double kills and larger streaks are clipped.
+    /// Other events are clipped as usual
+    #[arg(long, default_value_t = false)]
+    pub multi_kills_only: bool,
+    #[command(flatten)]
+    pub tesseract: TesseractOptions,
+    /// Directory with the reference images of the template search params, e.g. `elimination.png`
+    #[arg(long, value_hint = clap::ValueHint::DirPath)]
+    pub templates: Option<PathBuf>,
+}
+
+pub const DEFAULT_MULTI_KILL_WINDOW: f64 = 5.;
+
+impl DetectionArgs {
+    /// (clip_length_before, clip_length_after) of events of `kind`, the lengths of its search
+    /// param unless they are set in the args
+    pub fn clip_lengths(&self, kind: EventKind) -> (f64, f64) {
+        let (mut before, mut after) = SEARCH_PARAMS
+            .iter()
+            .chain(STATE_EVENTS)
+            .find(|s| s.kind() == Some(kind))
+            .map(SearchParam::clip_lengths)
+            .unwrap_or_default();
+        if kind == EventKind::Elimination {
+            before = self.elim_clip_duration;
+        }
+        // the last one wins when a kind is given more than once
+        let set = |lengths: &[(EventKind, f64)]| {
+            lengths
+                .iter()
+                .rev()
+                .find(|(k, _)| *k == kind)
+                .map(|(_, seconds)| *seconds)
+        };
+        if let Some(seconds) = set(&self.pre_roll) {
+            before = seconds;
+        }
+        if let Some(seconds) = set(&self.post_roll) {
+            after = seconds;
+        }
+        (before, after)
+    }
+}
+
+/// Parses `kind=seconds` of `--pre-roll` and `--post-roll`
+pub fn parse_event_seconds(value: &str) -> Result<(EventKind, f64), String> {
+    let (kind, seconds) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected kind=seconds, got {value:?}"))?;
+    let kind = EventKind::from_str(kind.trim(), true)?;
+    let seconds = seconds
+        .trim()
+        .parse::<f64>()
+        .map_err(|err| format!("invalid seconds {seconds:?}: {err}"))?;
+    if seconds < 0. {
+        return Err(format!("seconds can't be negative, got {seconds}"));
+    }
+    Ok((kind, seconds))
 }
 
 pub struct Resize {
-    height: u32,
+    pub(crate) height: u32,
 }
 
 pub struct BinarisationParams {
-    min_rgb: [u8; 3],
-    max_rgb: [u8; 3],
+    pub(crate) min_rgb: [u8; 3],
+    pub(crate) max_rgb: [u8; 3],
 }
 
 pub struct BrightnessContrastParams {
-    brightness: f64,
-    contrast: f64,
-    invert: bool,
+    pub(crate) brightness: f64,
+    pub(crate) contrast: f64,
+    pub(crate) invert: bool,
 }
 
 pub enum SearchParam<'a> {
     Text {
+        kind: EventKind,
         patterns: &'a [&'a str],
+        /// characters tesseract may recognize, all if `None`
+        whitelist: Option<&'a str>,
         timeout: f64,
         clip_length_before: f64,
         clip_length_after: f64,
@@ -69,8 +165,41 @@ pub enum SearchParam<'a> {
         brightness_contrast_params: Option<BrightnessContrastParams>,
         resize: Option<Resize>,
     },
+    /// a reference image searched for with normalized cross-correlation, e.g. a hud icon
+    Template {
+        kind: EventKind,
+        /// file name in the templates directory
+        template: &'a str,
+        /// lowest correlation counted as a match, 1 is a perfect match
+        threshold: f32,
+        timeout: f64,
+        clip_length_before: f64,
+        clip_length_after: f64,
+        search_area: SearchArea,
+        /// applied to the template as well
+        binarisation_params: Option<BinarisationParams>,
+        resize: Option<Resize>,
+    },
+    /// a hud state shown by a rectangle of a certain color, e.g. the red spectating bar
+    ColorRegion {
+        state: HudState,
+        search_area: SearchArea,
+        min_rgb: [u8; 3],
+        max_rgb: [u8; 3],
+        /// share of the pixels in the area that have to be in the color range
+        min_fraction: f64,
+    },
+    /// an event at the first searched frame showing a hud state, e.g. a death when spectating starts
+    StateEntered {
+        kind: EventKind,
+        state: HudState,
+        timeout: f64,
+        clip_length_before: f64,
+        clip_length_after: f64,
+    },
     #[allow(dead_code)]
     AveragePixelValue {
+        kind: EventKind,
         value: u8,
         clip_length_before: f64,
         clip_length_after: f64,
@@ -80,8 +209,48 @@ pub enum SearchParam<'a> {
 impl SearchParam<'_> {
     fn timeout(&self) -> f64 {
         match self {
-            SearchParam::Text { timeout, .. } => *timeout,
-            SearchParam::AveragePixelValue { .. } => 0.,
+            SearchParam::Text { timeout, .. }
+            | SearchParam::Template { timeout, .. }
+            | SearchParam::StateEntered { timeout, .. } => *timeout,
+            SearchParam::ColorRegion { .. } | SearchParam::AveragePixelValue { .. } => 0.,
+        }
+    }
+
+    /// `None` for params that only tell the hud state
+    fn kind(&self) -> Option<EventKind> {
+        match self {
+            SearchParam::Text { kind, .. }
+            | SearchParam::Template { kind, .. }
+            | SearchParam::StateEntered { kind, .. }
+            | SearchParam::AveragePixelValue { kind, .. } => Some(*kind),
+            SearchParam::ColorRegion { .. } => None,
+        }
+    }
+
+    /// (clip_length_before, clip_length_after)
+    fn clip_lengths(&self) -> (f64, f64) {
+        match self {
+            SearchParam::Text {
+                clip_length_before,
+                clip_length_after,
+                ..
+            }
+            | SearchParam::Template {
+                clip_length_before,
+                clip_length_after,
+                ..
+            }
+            | SearchParam::StateEntered {
+                clip_length_before,
+                clip_length_after,
+                ..
+            }
+            | SearchParam::AveragePixelValue {
+                clip_length_before,
+                clip_length_after,
+                ..
+            } => (*clip_length_before, *clip_length_after),
+            SearchParam::ColorRegion { .. } => (0., 0.),
         }
     }
 }
@@ -100,8 +269,87 @@ pub struct PreviewFrame {
     pub pixels: Box<[u8]>,
 }
 
+/// What a search param detects
+#[derive(
+    ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode, Serialize, Deserialize,
+)]
+pub enum EventKind {
+    Elimination,
+    Assist,
+    Win,
+    /// eliminated yourself
+    Death,
+    /// the loading screen of a match, only used to split the recording into matches
+    MatchStart,
+    /// the summary screen after a match
+    Summary,
+}
+
+/// Something the hud shows besides events, stored with the detections of the frames showing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode, Serialize, Deserialize)]
+pub enum HudState {
+    /// eliminated and watching a teammate
+    Spectating,
+}
+
+/// A single frame where a search param matched.
+///
+/// These are cached as is so clips can be derived again with different settings without rescanning
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct Detection {
+    /// time in seconds
+    pub time: f64,
+    pub kind: EventKind,
+    /// whether the red spectating bar was visible at the bottom of the frame,
+    /// kept besides `states` for older project files
+    pub spectating: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub states: Vec<HudState>,
+    /// what was read for text search params
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr: Option<OcrMatch>,
+    /// how many times the search param matched on the frame, e.g. one per kill feed entry
+    #[serde(default = "one")]
+    pub count: u32,
+    /// vertical centre of every matched text as a fraction of the search area, to tell kill
+    /// feed entries apart. Empty for search params without ocr
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rows: Vec<f32>,
+}
+
+fn one() -> u32 {
+    1
+}
+
+impl Detection {
+    /// whether a clip should be made for this detection with the given args
+    pub fn is_included(&self, args: &Args) -> bool {
+        match self.kind {
+            EventKind::Assist if !args.detection.include_assists => false,
+            EventKind::Elimination | EventKind::Assist => {
+                args.detection.include_spectating || !self.spectating
+            }
+            EventKind::Win => true,
+            EventKind::Death => args.detection.include_deaths,
+            EventKind::MatchStart | EventKind::Summary => false,
+        }
+    }
+
+    /// clip range in seconds before snapping to keyframes
+    fn clip_range(&self, args: &Args) -> [f64; 2] {
+        let (clip_length_before, clip_length_after) = args.detection.clip_lengths(self.kind);
+        [
+            self.time - clip_length_before,
+            self.time + clip_length_after,
+        ]
+    }
+}
+
 pub static SEARCH_PARAMS: &[SearchParam] = &[
     SearchParam::Text {
+        kind: EventKind::Elimination,
+        // the misreads only contain letters, so they are still read with the whitelist
+        whitelist: Some(LETTERS),
         patterns: &[
             "elim",
             "eliminated",
@@ -162,7 +410,10 @@ pub static SEARCH_PARAMS: &[SearchParam] = &[
         }),
     },
     SearchParam::Text {
-        patterns: &["assis", "ssist", "a58i8", "as5i", "amssr", "5i5t"],
+        kind: EventKind::Assist,
+        // misreads with digits like "a58i8" aren't read with the whitelist
+        patterns: &["assis", "ssist", "amssr"],
+        whitelist: Some(LETTERS),
         timeout: 0.,
         clip_length_before: 4.0,
         clip_length_after: 0.0,
@@ -184,7 +435,9 @@ pub static SEARCH_PARAMS: &[SearchParam] = &[
         }),
     },
     SearchParam::Text {
+        kind: EventKind::Win,
         patterns: &["winners", "qualif", "lified", "vinners", "linkers"],
+        whitelist: Some(LETTERS),
         timeout: 30.,
         clip_length_before: 10.,
         clip_length_after: 10.,
@@ -205,21 +458,59 @@ pub static SEARCH_PARAMS: &[SearchParam] = &[
             invert: true,
         }),
     },
-    // SearchParam::Text {
-    //     patterns: &["summary"],
-    //     timeout: 30.,
-    //     clip_length_after: 4.,
-    //     clip_length_before: 0.0,
-    //     search_area: SearchArea {
-    //         top: 0.038,
-    //         left: 0.0,
-    //         width: 0.159,
-    //         height: 0.0764,
-    //     },
-    // },
-    // SearchParam::Text {
-    //     patterns: &["the arena's calling!", "let's get into it!", "the arena has loaded", "buckle up"],
-    // },
+    // the announcer lines when a match loads, shown as subtitles
+    SearchParam::Text {
+        kind: EventKind::MatchStart,
+        // the lines sketched in the first version of the search params, without the
+        // exclamation marks that aren't whitelisted
+        patterns: &[
+            "the arena's calling",
+            "let's get into it",
+            "the arena has loaded",
+            "buckle up",
+        ],
+        whitelist: Some(SENTENCE_LETTERS),
+        timeout: 10.,
+        clip_length_before: 0.,
+        clip_length_after: 0.,
+        search_area: SearchArea {
+            top: 0.78,
+            left: 0.2,
+            width: 0.6,
+            height: 0.12,
+        },
+        resize: Some(Resize { height: 360 }),
+        binarisation_params: BinarisationParams {
+            min_rgb: [230, 230, 230],
+            max_rgb: [255, 255, 255],
+        },
+        brightness_contrast_params: None,
+    },
+    // the heading of the summary screen, where the first version of the search params read it
+    SearchParam::Text {
+        kind: EventKind::Summary,
+        patterns: &["summary"],
+        whitelist: Some(LETTERS),
+        timeout: 30.,
+        clip_length_before: 0.,
+        clip_length_after: 0.,
+        search_area: SearchArea {
+            top: 0.038,
+            left: 0.0,
+            width: 0.159,
+            height: 0.0764,
+        },
+        resize: Some(Resize { height: 360 }),
+        binarisation_params: BinarisationParams {
+            min_rgb: [248, 248, 248],
+            max_rgb: [255, 255, 255],
+        },
+        brightness_contrast_params: Some(BrightnessContrastParams {
+            brightness: -0.8,
+            contrast: 10.,
+            invert: true,
+        }),
+    },
 ];
 
 pub struct VideoProcessor {
@@ -227,7 +518,7 @@ pub struct VideoProcessor {
     pub progress_tx: crate::channel::Sender<ProgressMessage>,
     pub frame_tx: crate::channel::Sender<PreviewFrame>,
     pub keyframes: Box<[f64]>,
-    pub clips: Option<Box<[[f64; 2]]>>,
+    pub detections: Option<Box<[Detection]>>,
     pub video_duration: f64,
 }
 
@@ -243,21 +534,15 @@ impl VideoProcessor {
         info!("video duration {}", format_seconds(video_duration as i32));
 
         let keyframes;
-        let clips;
-        if let Some(cache) = clips_from_cache(&args.input) {
+        let detections;
+        if let Some(cache) = detections_from_cache(&args.input, args.cache_dir.as_deref()) {
             keyframes = cache.keyframes;
-            clips = cache
-                .clips
-                .get(&CacheKey {
-                    include_assists: args.include_assists,
-                    include_spectating: args.include_spectating,
-                })
-                .cloned();
+            detections = Some(cache.detections);
         } else {
             keyframes = decoder.key_frames();
             let is_sorted = keyframes.windows(2).all(|w| w[0] <= w[1]);
             assert!(is_sorted);
-            clips = None;
+            detections = None;
         }
 
         VideoProcessor {
@@ -265,7 +550,7 @@ impl VideoProcessor {
             progress_tx,
             frame_tx,
             keyframes,
-            clips,
+            detections,
             video_duration,
         }
     }
@@ -274,11 +559,18 @@ impl VideoProcessor {
         let video_duration = self.video_duration;
 
         let keyframes = self.keyframes;
-        if let Some(clips) = self.clips {
+        if let Some(detections) = self.detections {
+            info!("using cached detections");
+            let clips = clips_from_detections(&detections, &keyframes, &self.args);
+            let streaks = clip_streaks(&clips, &detections, self.args.detection.multi_kill_window);
+            let matches = segment_matches(&detections, video_duration);
             return ProcessResult {
                 clips,
+                detections,
                 keyframes,
                 input_duration: video_duration,
+                streaks,
+                matches,
             };
         }
 
@@ -331,7 +623,16 @@ impl VideoProcessor {
             let args = self.args.clone();
             let frame_tx = self.frame_tx.clone();
             let handle = std::thread::spawn(move || {
-                get_clip_times(args, seek_ts, start, end, progress_tx, frame_tx, hw_accel)
+                get_clip_times(
+                    args,
+                    seek_ts,
+                    start,
+                    end,
+                    progress_tx,
+                    frame_tx,
+                    hw_accel,
+                    Source::File,
+                )
             });
 
             join_handles.push(handle);
@@ -400,89 +701,205 @@ impl VideoProcessor {
             }
         }
 
-        let mut clips = Vec::<[f64; 2]>::new();
-        // combine clip times
+        let mut detections = Vec::<Detection>::new();
+        // combine detections, threads are in chronological order
         for handle in join_handles {
-            let mut new_clips = handle.join().unwrap().unwrap();
+            detections.extend(handle.join().unwrap().unwrap());
+        }
+        let detections = detections.into_boxed_slice();
 
-            // combine overlapping clip range
-            if let Some((last_clip_range, next_clip_range)) =
-                clips.last().zip(new_clips.first_mut())
-            {
-                if last_clip_range[1] >= next_clip_range[0] {
-                    next_clip_range[0] = last_clip_range[0];
-                    clips.pop();
-                }
-            }
+        cache_detections(detections.clone(), keyframes.clone(), &self.args);
 
-            clips.extend_from_slice(&new_clips);
-        }
+        let clips = clips_from_detections(&detections, &keyframes, &self.args);
+        let streaks = clip_streaks(&clips, &detections, self.args.detection.multi_kill_window);
+        let matches = segment_matches(&detections, video_duration);
 
-        // make clip start and end times be on i-frames (key frames)
-        for clip in clips.iter_mut() {
-            let start_index = keyframes
-                .binary_search_by(|ts| ts.partial_cmp(&clip[0]).expect("Couldn't compare values"))
-                .unwrap_or_else(|err| err)
-                .min(keyframes.len() - 1)
-                .max(0);
-            let mut start_time = keyframes[start_index];
-            if start_time - clip[0] > 0.5 {
-                start_time = keyframes[start_index.saturating_sub(1)];
-            }
-            let end_index = keyframes
-                .binary_search_by(|ts| ts.partial_cmp(&clip[1]).expect("Couldn't compare values"))
-                .unwrap_or_else(|err| err)
-                .min(keyframes.len() - 1);
-            let end_time = keyframes[end_index];
-            clip[0] = start_time;
-            clip[1] = end_time;
-        }
-
-        // combine overlapping clips
-        for index in (0..clips.len().saturating_sub(1)).rev() {
-            let next_clip = clips[index + 1];
-            let clip = &mut clips[index];
-            // overlap
-            if next_clip[0] <= clip[1] {
-                info!("found overlapping clip");
-                clip[1] = next_clip[1];
-                info!(
-                    "new clip duration: {}",
-                    format_seconds((clip[1] - clip[0]) as i32)
-                );
-                clips.remove(index + 1);
-            }
+        ProcessResult {
+            clips,
+            detections,
+            keyframes,
+            input_duration: video_duration,
+            streaks,
+            matches,
         }
+    }
 
-        for clip in clips.iter() {
-            info!(
-                "clip at: {:.2}, duration: {:.2}",
-                clip[0],
-                clip[1] - clip[0],
-            );
+    /// Detects events while the input is still being recorded.
+    ///
+    /// A single thread follows the end of the file, preview frames of detections are sent as
+    /// they are found. Processing finishes once the file hasn't grown for `idle_timeout`, the
+    /// keyframes are then read from the finished recording
+    pub fn process_growing(self, hw_accel: bool, idle_timeout: Duration) -> ProcessResult {
+        let (progress_tx, progress_rx) = crate::channel::bounded::<f32>(1);
+        let args = self.args.clone();
+        let frame_tx = self.frame_tx.clone();
+        let handle = std::thread::spawn(move || {
+            get_clip_times(
+                args,
+                0.,
+                0.,
+                f64::INFINITY,
+                progress_tx,
+                frame_tx,
+                hw_accel,
+                Source::Growing(idle_timeout),
+            )
+        });
+
+        while let Ok(progress) = progress_rx.recv() {
+            // speed is unknown, the recording grows while it's processed
+            self.progress_tx
+                .send(ProgressMessage {
+                    progress,
+                    speed: 0.,
+                })
+                .ok();
         }
 
-        let clips = clips.into_boxed_slice();
+        let detections = handle
+            .join()
+            .unwrap()
+            .map_err(|err| error!("failed to follow {}: {err}", self.args.input.display()))
+            .unwrap_or_default()
+            .into_boxed_slice();
+
+        let decoder = VideoDecoder::open(&self.args.input, false).unwrap();
+        let input_duration = decoder.video_duration();
+        let keyframes = decoder.key_frames();
 
-        cache_clips(clips.clone(), keyframes.clone(), &self.args);
+        cache_detections(detections.clone(), keyframes.clone(), &self.args);
+        let clips = clips_from_detections(&detections, &keyframes, &self.args);
+        let streaks = clip_streaks(&clips, &detections, self.args.detection.multi_kill_window);
+        let matches = segment_matches(&detections, input_duration);
 
         ProcessResult {
             clips,
+            detections,
             keyframes,
-            input_duration: video_duration,
+            input_duration,
+            streaks,
+            matches,
+        }
+    }
+}
+
+/// Turns raw detections into clip ranges for the given args.
+///
+/// Eliminations of a multi-kill share one clip, overlapping clips are merged and start and end
+/// times are moved onto i-frames (key frames)
+pub fn clips_from_detections(
+    detections: &[Detection],
+    keyframes: &[f64],
+    args: &Args,
+) -> Box<[[f64; 2]]> {
+    let streaks = kill_streaks(detections, args.detection.multi_kill_window);
+    let mut ranges: Vec<[f64; 2]> = streaks
+        .iter()
+        .filter(|streak| streak.is_multi_kill())
+        .map(|streak| {
+            let (before, after) = args.detection.clip_lengths(EventKind::Elimination);
+            [streak.start - before, streak.end + after]
+        })
+        .collect();
+    // the streaks above already cover the eliminations of multi-kills
+    let multi_kills_only = args.detection.multi_kills_only;
+    ranges.extend(
+        detections
+            .iter()
+            .filter(|d| d.is_included(args))
+            .filter(|d| !(multi_kills_only && d.kind == EventKind::Elimination))
+            .map(|d| d.clip_range(args)),
+    );
+    ranges.sort_by(|a, b| a[0].total_cmp(&b[0]));
+
+    let mut clips = Vec::<[f64; 2]>::new();
+    for clip_range in ranges {
+        // overlap
+        if let Some(last_clip_range) = clips.last_mut() {
+            if last_clip_range[1] >= clip_range[0] {
+                last_clip_range[1] = last_clip_range[1].max(clip_range[1]);
+                continue;
+            }
+        }
+
+        clips.push([clip_range[0].max(0.), clip_range[1]]);
+    }
+
+    if keyframes.is_empty() {
+        return clips.into_boxed_slice();
+    }
+
+    // make clip start and end times be on i-frames (key frames)
+    for clip in clips.iter_mut() {
+        let start_index = keyframes
+            .binary_search_by(|ts| ts.partial_cmp(&clip[0]).expect("Couldn't compare values"))
+            .unwrap_or_else(|err| err)
+            .min(keyframes.len() - 1)
+            .max(0);
+        let mut start_time = keyframes[start_index];
+        if start_time - clip[0] > 0.5 {
+            start_time = keyframes[start_index.saturating_sub(1)];
         }
+        let end_index = keyframes
+            .binary_search_by(|ts| ts.partial_cmp(&clip[1]).expect("Couldn't compare values"))
+            .unwrap_or_else(|err| err)
+            .min(keyframes.len() - 1);
+        let end_time = keyframes[end_index];
+        clip[0] = start_time;
+        clip[1] = end_time;
     }
+
+    // combine overlapping clips
+    for index in (0..clips.len().saturating_sub(1)).rev() {
+        let next_clip = clips[index + 1];
+        let clip = &mut clips[index];
+        // overlap
+        if next_clip[0] <= clip[1] {
+            info!("found overlapping clip");
+            clip[1] = next_clip[1];
+            info!(
+                "new clip duration: {}",
+                format_seconds((clip[1] - clip[0]) as i32)
+            );
+            clips.remove(index + 1);
+        }
+    }
+
+    for clip in clips.iter() {
+        info!(
+            "clip at: {:.2}, duration: {:.2}",
+            clip[0],
+            clip[1] - clip[0],
+        );
+    }
+
+    clips.into_boxed_slice()
 }
 
-#[derive(serde::Serialize)]
+#[derive(serde::Serialize, Clone)]
 pub struct ProcessResult {
-    clips: Box<[[f64; 2]]>,
-    keyframes: Box<[f64]>,
-    input_duration: f64,
+    pub clips: Box<[[f64; 2]]>,
+    pub detections: Box<[Detection]>,
+    pub keyframes: Box<[f64]>,
+    pub input_duration: f64,
+    /// the largest streak in each clip, see [`clip_streaks`]
+    pub streaks: Box<[ClipStreak]>,
+    /// the matches of the recording in chronological order, see [`segment_matches`]
+    pub matches: Box<[MatchSegment]>,
+}
+
+/// What [`get_clip_times`] reads from
+pub(crate) enum Source {
+    File,
+    /// a recording that is still being written, finished once it hasn't grown for this long
+    Growing(Duration),
+    /// a network stream, copied into the buffer while it's processed
+    Live(Arc<Mutex<RollingBuffer>>),
 }
 
 //noinspection DuplicatedCode
-fn get_clip_times(
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn get_clip_times(
     args: Args,
     seek_ts: f64,
     start_ts: f64,
@@ -490,23 +907,16 @@ fn get_clip_times(
     progress_tx: crate::channel::Sender<f32>,
     frame_tx: crate::channel::Sender<PreviewFrame>,
     hw_accel: bool,
-) -> Result<Vec<[f64; 2]>, ffmpeg::Error> {
-    let clip_length = 4.0;
-    let mut clips = Vec::new();
-
-    let push_clip = |clips: &mut Vec<[f64; 2]>, clip_range: [f64; 2]| {
-        // overlap
-        if let Some(last_clip_range) = clips.last_mut() {
-            if last_clip_range[1] >= clip_range[0] {
-                last_clip_range[1] = clip_range[1];
-                return;
-            }
-        }
-
-        clips.push([clip_range[0].max(0.), clip_range[1]]);
-    };
+    source: Source,
+) -> Result<Vec<Detection>, ffmpeg::Error> {
+    let mut detections = Vec::new();
 
     let mut decoder = VideoDecoder::open(&args.input, hw_accel)?;
+    match &source {
+        Source::File => {}
+        Source::Growing(idle_timeout) => decoder.follow(*idle_timeout),
+        Source::Live(buffer) => decoder.record(buffer.clone()),
+    }
     let initial_format = decoder.initial_format();
     if seek_ts > 0. {
         decoder.seek(start_ts);
@@ -518,31 +928,59 @@ fn get_clip_times(
     let time_base = decoder.time_base();
     let mut rgb_scaler = decoder.rgb_scaler()?;
     // 10 times per second
-    let step_size = frame_rate as usize / 10;
+    let step_size = (frame_rate as usize / 10).max(1);
     info!("step_size: {step_size}");
 
     info!("Resolution {width}x{height}");
 
-    let mut tess = Tesseract::new(None, Some("eng")).unwrap();
-
-    let mut last_times = vec![-50000.; SEARCH_PARAMS.len()];
-
-    'frame: for mut frame in decoder.decode_iter().step_by(step_size) {
+    let mut tess = args
+        .detection
+        .tesseract
+        .build(TESSERACT_LANGUAGE)
+        .unwrap_or_else(|err| {
+            panic!("tesseract language {TESSERACT_LANGUAGE} not available: {err}")
+        });
+
+    let mut templates = Templates::new(args.detection.templates.clone());
+
+    // indexed like the search params, followed by the template params and state events
+    let max_params = SEARCH_PARAMS.len();
+    let mut last_times = vec![-50000.; max_params + TEMPLATE_PARAMS.len() + STATE_EVENTS.len()];
+    let state_events_offset = max_params + TEMPLATE_PARAMS.len();
+    let timeouts: Vec<f64> = SEARCH_PARAMS
+        .iter()
+        .chain(TEMPLATE_PARAMS)
+        .chain(STATE_EVENTS)
+        .map(|search| search.timeout())
+        .collect();
+    // `None` until the first searched frame, a thread starting while spectating isn't a death
+    let mut previous_states: Option<Vec<HudState>> = None;
+
+    let mut frame_index = 0;
+    'frame: while let Ok(mut frame) = decoder.decode_frame() {
+        frame_index += 1;
+        if (frame_index - 1) % step_size != 0 {
+            continue;
+        }
         let time = t_to_secs(frame.pts().unwrap(), time_base);
 
         if time < start_ts {
             continue;
         }
 
-        let progress = (time - start_ts) / (end_ts - start_ts) * 100.;
-        progress_tx.send(progress.min(100.) as f32).ok();
+        // the end of a followed recording isn't known yet
+        let progress = decoder
+            .follow_progress()
+            .unwrap_or(((time - start_ts) / (end_ts - start_ts) * 100.) as f32);
+        progress_tx.send(progress.min(100.)).ok();
 
-        // if any timeouts are active continue
-        if SEARCH_PARAMS
+        // a param isn't searched for again until its own timeout is over
+        let active: Vec<bool> = last_times
             .iter()
-            .enumerate()
-            .any(|(index, s)| last_times[index] + s.timeout() > time)
-        {
+            .zip(&timeouts)
+            .map(|(last_time, timeout)| last_time + timeout > time)
+            .collect();
+        if active.iter().all(|active| *active) {
             continue;
         }
 
@@ -550,211 +988,148 @@ fn get_clip_times(
             .map_err(|err| error!("failed to convert frame to rgb: {err:#?}"))
             .unwrap();
 
-        for (index, search) in SEARCH_PARAMS.iter().enumerate() {
-            match search {
-                SearchParam::Text {
-                    search_area,
-                    patterns,
-                    clip_length_after,
-                    clip_length_before,
-                    timeout: _timeout,
-                    resize,
-                    binarisation_params,
-                    brightness_contrast_params,
-                } => {
-                    // // remove duplicates
-                    // let mut patts = patterns.to_vec();
-                    // for i in (0..patts.len()).rev() {
-                    //     let curr = patts[i];
-                    //     if patts
-                    //         .iter()
-                    //         .enumerate()
-                    //         .any(|(index, pat)| index != i && curr.contains(*pat))
-                    //     {
-                    //         patts.remove(i);
-                    //     }
-                    // }
-                    // println!("{patts:?}");
-                    let clip_length_before =
-                        if patterns.contains(&"iminated") || patterns.contains(&"ass") {
-                            args.elim_clip_duration
-                        } else {
-                            *clip_length_before
-                        };
-
-                    if !args.include_assists && patterns.contains(&"ass") {
-                        continue;
-                    }
-                    if !args.include_spectating && !patterns.contains(&"winners") {
-                        // check for spectating
-                        let last_row = &frame_data[frame_data.len() - (width as usize * 3)..];
-                        assert_eq!(last_row.len(), width as usize * 3);
-                        let color_sum =
-                            last_row
-                                .chunks_exact(3)
-                                .fold([0_i32, 0_i32, 0_i32], |mut acc, x| {
-                                    acc[0] += x[0] as i32;
-                                    acc[1] += x[1] as i32;
-                                    acc[2] += x[2] as i32;
-
-                                    acc
-                                });
-                        let avg_color = [
-                            color_sum[0] / width,
-                            color_sum[1] / width,
-                            color_sum[2] / width,
-                        ];
-                        let r = avg_color[0];
-                        let g = avg_color[1];
-                        let b = avg_color[2];
-                        let is_spectating =
-                            r > 173 && r < 205 && g > 4 && g < 45 && b > 50 && b < 76;
-                        // rgb_max = [r.max(rgb_max[0]), g.max(rgb_max[1]), b.max(rgb_max[2])];
-                        // rgb_min = [r.min(rgb_min[0]), g.min(rgb_min[1]), b.min(rgb_min[2])];
-                        if is_spectating {
-                            continue;
-                        }
+        let states: Vec<HudState> = HUD_STATES
+            .iter()
+            .filter_map(|search| find_color_region(search, &frame_data, width, height))
+            .collect();
+        let spectating = states.contains(&HudState::Spectating);
+        let entered = previous_states.as_ref().and_then(|previous| {
+            STATE_EVENTS
+                .iter()
+                .enumerate()
+                .find_map(|(index, search)| match search {
+                    SearchParam::StateEntered { kind, state, .. }
+                        if states.contains(state)
+                            && !previous.contains(state)
+                            && !active[state_events_offset + index] =>
+                    {
+                        Some((index, *kind))
                     }
-                    let mut pixels;
-                    let (pixels, width, height) = if let Some(resize) = resize {
-                        let dst_height = resize.height as i32;
-                        let scale_factor = height as f64 / resize.height as f64;
-                        let dst_width = (width as f64 / scale_factor) as u32;
-                        pixels = scale_frame(
-                            &frame_data,
-                            width,
-                            height,
-                            resize.height,
-                            fr::ResizeAlg::Convolution(fr::FilterType::Bilinear),
-                        );
-
-                        (pixels.as_mut_slice(), dst_width as i32, dst_height)
-                    } else {
-                        pixels = frame_data.clone();
-                        (pixels.as_mut_slice(), width, height)
-                    };
+                    _ => None,
+                })
+        });
+        previous_states = Some(states.clone());
+
+        let mut push_detection = |index: usize,
+                                  kind: EventKind,
+                                  ocr: Option<OcrMatch>,
+                                  count: u32,
+                                  rows: Vec<f32>,
+                                  frame_data: Vec<u8>| {
+            info!(
+                "thread progress: {:.1}%",
+                (time - start_ts) / (end_ts - start_ts) * 100.
+            );
+            last_times[index] = time;
+            let detection = Detection {
+                time,
+                kind,
+                spectating,
+                states: states.clone(),
+                ocr,
+                count,
+                rows,
+            };
+            if detection.is_included(&args) {
+                // the buffered stream around a detection is needed to write its clip
+                if let Source::Live(buffer) = &source {
+                    buffer.lock().unwrap().keep(detection.clip_range(&args));
+                }
+                frame_tx
+                    .send(PreviewFrame {
+                        pts: frame.pts().unwrap(),
+                        width: frame.width(),
+                        height: frame.height(),
+                        pixels: frame_data.into_boxed_slice(),
+                    })
+                    .ok();
+            }
+            detections.push(detection);
+        };
+
+        if let Some((index, kind)) = entered {
+            info!("found {kind:?} at: {}", format_seconds(time as i32));
+            push_detection(
+                state_events_offset + index,
+                kind,
+                None,
+                1,
+                Vec::new(),
+                frame_data,
+            );
+            continue 'frame;
+        }
 
-                    let pixels_clone = brightness_contrast_params
-                        .as_ref()
-                        .map(|_| Vec::from_iter(pixels.iter().copied()));
+        // templates are much cheaper than tesseract
+        for (index, search) in TEMPLATE_PARAMS.iter().enumerate() {
+            if active[max_params + index] {
+                continue;
+            }
+            if let Some((kind, score, count)) =
+                find_template(&mut templates, search, &frame_data, width, height)
+            {
+                info!(
+                    "found template {count} time(s) with correlation {score:.2} at: {}",
+                    format_seconds(time as i32)
+                );
+                push_detection(
+                    max_params + index,
+                    kind,
+                    None,
+                    count,
+                    Vec::new(),
+                    frame_data,
+                );
+                continue 'frame;
+            }
+        }
 
-                    frame_binarisation(
-                        pixels,
-                        binarisation_params.min_rgb,
-                        binarisation_params.max_rgb,
+        for (index, search) in SEARCH_PARAMS.iter().enumerate() {
+            if active[index] {
+                continue;
+            }
+            match search {
+                SearchParam::Text { kind, .. } => {
+                    let (next, found) = find_text(
+                        tess,
+                        search,
+                        &frame_data,
+                        width,
+                        height,
+                        args.detection.tesseract.min_confidence,
                     );
-
-                    let left = (search_area.left * width as f64) as i32;
-                    let top = (search_area.top * height as f64) as i32;
-                    let inner_width = (search_area.width * width as f64) as i32;
-                    let inner_height = (search_area.height * height as f64) as i32;
-
-                    // info!("search_area left: {left}, top: {top}, width: {inner_width}, height: {inner_height}");
-
-                    tess = tess
-                        .set_frame(pixels, width, height, 3, width * 3)
-                        .unwrap()
-                        .set_rectangle(left, top, inner_width, inner_height)
-                        .recognize()
-                        .unwrap();
-                    let Ok(result) = tess.get_text() else {
-                        continue 'frame;
-                    };
-
-                    // if patterns.contains(&"assist") || patterns.contains(&"iminated") {
-                    //     println!("{}", result.to_lowercase());
-                    // }
-
-                    if patterns
-                        .iter()
-                        .any(|pat| result.to_lowercase().contains(pat))
-                    {
-                        info!("found matching text at: {}", format_seconds(time as i32));
+                    tess = next;
+                    if let Some((found, rows)) = found {
                         info!(
-                            "thread progress: {:.1}%",
-                            (time - start_ts) / (end_ts - start_ts) * 100.
-                        );
-                        last_times[index] = time;
-                        push_clip(
-                            &mut clips,
-                            [time - clip_length_before, time + clip_length_after],
+                            "found matching text {:?} ({:.0}%) at: {}",
+                            found.text,
+                            found.confidence,
+                            format_seconds(time as i32)
                         );
-                        frame_tx
-                            .send(PreviewFrame {
-                                pts: frame.pts().unwrap(),
-                                width: frame.width(),
-                                height: frame.height(),
-                                pixels: frame_data.into_boxed_slice(),
-                            })
-                            .ok();
+                        let count = rows.len() as u32;
+                        push_detection(index, *kind, Some(found), count, rows, frame_data);
                         continue 'frame;
                     }
-
-                    if let Some(BrightnessContrastParams {
-                        brightness,
-                        contrast,
-                        invert,
-                    }) = *brightness_contrast_params
-                    {
-                        let mut pixels = pixels_clone.unwrap();
-                        frame_brightness_contrast(&mut pixels, brightness, contrast, invert);
-                        tess = tess
-                            .set_frame(&pixels, width, height, 3, width * 3)
-                            .unwrap()
-                            .set_rectangle(left, top, inner_width, inner_height)
-                            .recognize()
-                            .unwrap();
-
-                        let Ok(result) = tess.get_text() else {
-                            continue 'frame;
-                        };
-
-                        if patterns
-                            .iter()
-                            .any(|pat| result.to_lowercase().contains(pat))
-                        {
-                            info!(
-                                "found matching text using brightness/contrast at: {}",
-                                format_seconds(time as i32)
-                            );
-                            info!(
-                                "thread progress: {:.1}%",
-                                (time - start_ts) / (end_ts - start_ts) * 100.
-                            );
-                            last_times[index] = time;
-                            push_clip(
-                                &mut clips,
-                                [time - clip_length_before, time + clip_length_after],
-                            );
-                            frame_tx
-                                .send(PreviewFrame {
-                                    pts: frame.pts().unwrap(),
-                                    width: frame.width(),
-                                    height: frame.height(),
-                                    pixels: frame_data.into_boxed_slice(),
-                                })
-                                .ok();
-                            continue 'frame;
-                        }
-                    }
                 }
                 SearchParam::AveragePixelValue {
+                    kind,
                     value,
-                    clip_length_before,
-                    clip_length_after,
+                    clip_length_before: _,
+                    clip_length_after: _,
                 } => {
-                    let frame_data = frame.data(0);
+                    let plane = frame.data(0);
                     let average: u64 =
-                        frame_data.iter().map(|v| *v as u64).sum::<u64>() / frame_data.len() as u64;
+                        plane.iter().map(|v| *v as u64).sum::<u64>() / plane.len() as u64;
                     if average as u8 >= *value {
                         info!("found average pixel value: {}", { average });
-                        push_clip(
-                            &mut clips,
-                            [time - clip_length_before, time + clip_length_after],
-                        );
+                        push_detection(index, *kind, None, 1, Vec::new(), frame_data);
                         continue 'frame;
                     }
                 }
+                // searched for before the text params
+                SearchParam::Template { .. }
+                | SearchParam::ColorRegion { .. }
+                | SearchParam::StateEntered { .. } => {}
             }
         }
 
@@ -765,19 +1140,256 @@ fn get_clip_times(
         }
     }
 
-    for clip in clips.iter() {
+    for detection in detections.iter() {
         info!(
-            "clip at: {}, duration: {}",
-            format_seconds(clip[0] as i32),
-            format_seconds((clip[1] - clip[0]) as i32)
+            "{:?} at: {}, spectating: {}",
+            detection.kind,
+            format_seconds(detection.time as i32),
+            detection.spectating
         );
-        let duration = clip[1] - clip[0];
-        if duration < clip_length {
-            info!("duration < clip_length, duration: {duration}, clip_length: {clip_length}");
+    }
+
+    Ok(detections)
+}
+
+/// Runs tesseract on the search area of a [`SearchParam::Text`], first on the binarised frame and
+/// then with its brightness/contrast params. Tesseract is handed back as its builder consumes it
+fn find_text(
+    mut tess: Tesseract,
+    search: &SearchParam,
+    frame_data: &[u8],
+    width: i32,
+    height: i32,
+    min_confidence: f32,
+) -> (Tesseract, Option<(OcrMatch, Vec<f32>)>) {
+    let SearchParam::Text {
+        search_area,
+        patterns,
+        whitelist,
+        resize,
+        binarisation_params,
+        brightness_contrast_params,
+        ..
+    } = search
+    else {
+        return (tess, None);
+    };
+
+    let mut pixels;
+    let (pixels, width, height) = if let Some(resize) = resize {
+        let dst_height = resize.height as i32;
+        let scale_factor = height as f64 / resize.height as f64;
+        let dst_width = (width as f64 / scale_factor) as u32;
+        pixels = scale_frame(
+            frame_data,
+            width,
+            height,
+            resize.height,
+            fr::ResizeAlg::Convolution(fr::FilterType::Bilinear),
+        );
+
+        (pixels.as_mut_slice(), dst_width as i32, dst_height)
+    } else {
+        pixels = frame_data.to_vec();
+        (pixels.as_mut_slice(), width, height)
+    };
+
+    let pixels_clone = brightness_contrast_params
+        .as_ref()
+        .map(|_| Vec::from_iter(pixels.iter().copied()));
+
+    frame_binarisation(
+        pixels,
+        binarisation_params.min_rgb,
+        binarisation_params.max_rgb,
+    );
+
+    let left = (search_area.left * width as f64) as i32;
+    let top = (search_area.top * height as f64) as i32;
+    let inner_width = (search_area.width * width as f64) as i32;
+    let inner_height = (search_area.height * height as f64) as i32;
+    let area = [left, top, inner_width, inner_height];
+
+    tess = set_whitelist(tess, *whitelist)
+        .unwrap()
+        .set_frame(pixels, width, height, 3, width * 3)
+        .unwrap()
+        .set_rectangle(left, top, inner_width, inner_height)
+        .recognize()
+        .unwrap();
+    if let Some(found) = find_word(&mut tess, patterns, area, min_confidence) {
+        return (tess, Some(found));
+    }
+
+    if let Some(BrightnessContrastParams {
+        brightness,
+        contrast,
+        invert,
+    }) = *brightness_contrast_params
+    {
+        let mut pixels = pixels_clone.unwrap();
+        frame_brightness_contrast(&mut pixels, brightness, contrast, invert);
+        tess = tess
+            .set_frame(&pixels, width, height, 3, width * 3)
+            .unwrap()
+            .set_rectangle(left, top, inner_width, inner_height)
+            .recognize()
+            .unwrap();
+
+        if let Some(found) = find_word(&mut tess, patterns, area, min_confidence) {
+            info!("found matching text using brightness/contrast");
+            return (tess, Some(found));
         }
     }
 
-    Ok(clips)
+    (tess, None)
+}
+
+/// Looks for `patterns` in the words of the last recognized image, see [`match_words`]
+fn find_word(
+    tess: &mut Tesseract,
+    patterns: &[&str],
+    area: [i32; 4],
+    min_confidence: f32,
+) -> Option<(OcrMatch, Vec<f32>)> {
+    let words = ocr::words(tess)
+        .map_err(|err| error!("failed to get tesseract words: {err}"))
+        .ok()?;
+    match_words(&words, patterns, area, min_confidence)
+}
+
+/// The first of `words` containing one of `patterns` with the vertical centre of every match as
+/// a fraction of `area`, those tesseract is less than `min_confidence` sure about are rejected
+fn match_words(
+    words: &[Word],
+    patterns: &[&str],
+    area: [i32; 4],
+    min_confidence: f32,
+) -> Option<(OcrMatch, Vec<f32>)> {
+    let found = find_all(words, patterns);
+    let (confident, rejected): (Vec<Word>, Vec<Word>) = found
+        .into_iter()
+        .partition(|word| word.confidence >= min_confidence);
+    for word in &rejected {
+        info!(
+            "rejected {:?} with confidence {:.0}",
+            word.text, word.confidence
+        );
+    }
+    let rows = confident
+        .iter()
+        .map(|word| {
+            let bounds = word.to_match(area).bounds;
+            bounds[1] + bounds[3] / 2.
+        })
+        .collect();
+    Some((confident.first()?.to_match(area), rows))
+}
+
+/// Searches for the reference image of a [`SearchParam::Template`], returns its best
+/// correlation and how often it matched if it's above the threshold
+fn find_template(
+    templates: &mut Templates,
+    search: &SearchParam<'static>,
+    frame_data: &[u8],
+    width: i32,
+    height: i32,
+) -> Option<(EventKind, f32, u32)> {
+    let SearchParam::Template {
+        kind,
+        template,
+        threshold,
+        search_area,
+        binarisation_params,
+        resize,
+        ..
+    } = search
+    else {
+        return None;
+    };
+    let template = templates.get(template, binarisation_params.as_ref())?;
+
+    let mut pixels;
+    let (pixels, width, height) = if let Some(resize) = resize {
+        let scale_factor = height as f64 / resize.height as f64;
+        let dst_width = (width as f64 / scale_factor) as i32;
+        pixels = scale_frame(
+            frame_data,
+            width,
+            height,
+            resize.height,
+            fr::ResizeAlg::Convolution(fr::FilterType::Bilinear),
+        );
+        (pixels.as_mut_slice(), dst_width, resize.height as i32)
+    } else {
+        pixels = frame_data.to_vec();
+        (pixels.as_mut_slice(), width, height)
+    };
+    if let Some(params) = binarisation_params {
+        frame_binarisation(pixels, params.min_rgb, params.max_rgb);
+    }
+
+    let left = (search_area.left * width as f64) as usize;
+    let top = (search_area.top * height as f64) as usize;
+    let area = [
+        left,
+        top,
+        ((search_area.width * width as f64) as usize).min(width as usize - left),
+        ((search_area.height * height as f64) as usize).min(height as usize - top),
+    ];
+    let matches = template.matches(pixels, width as usize, area, *threshold);
+    Some((*kind, *matches.first()?, matches.len() as u32))
+}
+
+/// The state of a [`SearchParam::ColorRegion`] if enough of its area has its color
+fn find_color_region(
+    search: &SearchParam,
+    frame_data: &[u8],
+    width: i32,
+    height: i32,
+) -> Option<HudState> {
+    let SearchParam::ColorRegion {
+        state,
+        search_area,
+        min_rgb,
+        max_rgb,
+        min_fraction,
+    } = search
+    else {
+        return None;
+    };
+
+    let fraction = area_fraction(frame_data, width, height, search_area, *min_rgb, *max_rgb);
+    (fraction >= *min_fraction).then_some(*state)
+}
+
+/// The share of the pixels of `search_area` with all channels between `min_rgb` and `max_rgb`
+fn area_fraction(
+    frame_data: &[u8],
+    width: i32,
+    height: i32,
+    search_area: &SearchArea,
+    min_rgb: [u8; 3],
+    max_rgb: [u8; 3],
+) -> f64 {
+    // rounded outwards so thin areas like the bottom row cover at least one pixel
+    let (width, height) = (width as usize, height as usize);
+    let left = ((search_area.left * width as f64) as usize).min(width - 1);
+    let top = ((search_area.top * height as f64) as usize).min(height - 1);
+    let right = (((search_area.left + search_area.width) * width as f64).ceil() as usize)
+        .clamp(left + 1, width);
+    let bottom = (((search_area.top + search_area.height) * height as f64).ceil() as usize)
+        .clamp(top + 1, height);
+
+    let matching = frame_data
+        .chunks_exact(width * 3)
+        .skip(top)
+        .take(bottom - top)
+        .flat_map(|row| row[left * 3..right * 3].chunks_exact(3))
+        .filter(|rgb| (0..3).all(|c| rgb[c] >= min_rgb[c] && rgb[c] <= max_rgb[c]))
+        .count();
+    let total = (right - left) * (bottom - top);
+    matching as f64 / total as f64
 }
 
 pub fn write_clips(input: &PathBuf, output: &PathBuf, clips: &[[f64; 2]], keyframes: &[f64]) {
@@ -795,6 +1407,66 @@ pub fn write_clips(input: &PathBuf, output: &PathBuf, clips: &[[f64; 2]], keyfra
     clip_writer.write_trailer();
 }
 
+/// Clips of one recording in a montage
+pub struct MontageSource<'a> {
+    pub input: &'a PathBuf,
+    pub clips: &'a [[f64; 2]],
+    pub keyframes: &'a [f64],
+}
+
+/// Writes the clips of several recordings one after another into a single output.
+/// Recordings with the same codec parameters are stream copied, otherwise all clips are
+/// re-encoded to the resolution and frame rate of the first recording
+pub fn write_montage(sources: &[MontageSource], output: &PathBuf) -> Result<(), ffmpeg::Error> {
+    let sources: Vec<&MontageSource> = sources
+        .iter()
+        .filter(|source| !source.clips.is_empty())
+        .collect();
+    let Some(first) = sources.first() else {
+        return Ok(());
+    };
+    info!("writing montage of {} recording(s)...", sources.len());
+
+    let inputs: Vec<&Path> = sources
+        .iter()
+        .map(|source| source.input.as_path())
+        .collect();
+    if !can_stream_copy(&inputs)? {
+        warn!("recordings have different codec parameters, re-encoding the montage");
+        let mut encoder = MontageEncoder::new(first.input, output)?;
+        for source in sources.iter() {
+            encoder.write_clips(source.input, source.clips)?;
+        }
+        return encoder.finish();
+    }
+
+    let mut clip_writer = ClipWriter::new(first.input, output);
+    for (i, source) in sources.iter().enumerate() {
+        if i > 0 {
+            clip_writer.set_input(source.input)?;
+        }
+        // keyframes are computed by the writer if they are unknown
+        if !source.keyframes.is_empty() {
+            clip_writer.set_keyframes(source.keyframes);
+        }
+        for clip in source.clips.iter() {
+            clip_writer.write_clip(clip[0], clip[1]);
+        }
+    }
+
+    clip_writer.write_trailer();
+    Ok(())
+}
+
+/// `<input>.eclipper_trimmed.<ext>`, the default output path of a montage
+pub fn trimmed_path(input: &Path) -> PathBuf {
+    let extension = input
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_else(|| "mp4".to_string());
+    input.with_extension(format!("eclipper_trimmed.{extension}"))
+}
+
 fn to_rgb(
     frame: &Video,
     rgb_scaler: &mut scaling::Context,
@@ -915,3 +1587,87 @@ mod base64 {
             .map_err(serde::de::Error::custom)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The words tesseract reads from `lines`, one line after the other
+    fn words(lines: &[&str]) -> Vec<Word> {
+        lines
+            .iter()
+            .zip(0..)
+            .flat_map(|(line, line_index)| {
+                line.split_whitespace()
+                    .zip(0..)
+                    .map(move |(text, index)| Word {
+                        line: (1, 1, line_index),
+                        text: text.to_string(),
+                        confidence: 90.,
+                        bounds: [index * 100, line_index as i32 * 40, 90, 30],
+                    })
+            })
+            .collect()
+    }
+
+    fn find(kind: EventKind, lines: &[&str]) -> Option<(OcrMatch, Vec<f32>)> {
+        let patterns = SEARCH_PARAMS
+            .iter()
+            .find_map(|search| match search {
+                SearchParam::Text {
+                    kind: search_kind,
+                    patterns,
+                    ..
+                } if *search_kind == kind => Some(*patterns),
+                _ => None,
+            })
+            .unwrap();
+        match_words(&words(lines), patterns, [0, 0, 1000, 100], 60.)
+    }
+
+    #[test]
+    fn match_start_matches_whole_lines() {
+        let (found, _) = find(EventKind::MatchStart, &["THE ARENA'S CALLING"]).unwrap();
+        assert_eq!(found.text, "THE ARENA'S CALLING");
+        assert!(find(EventKind::MatchStart, &["BUCKLE", "UP"]).is_none());
+    }
+
+    #[test]
+    fn every_kill_feed_entry_has_a_row() {
+        let lines = ["ELIMINATED PLAYER", "ELIMINATED OTHER"];
+        let (found, rows) = find(EventKind::Elimination, &lines).unwrap();
+        assert_eq!(found.text, "ELIMINATED");
+        assert_eq!(rows.len(), 2);
+        assert!((rows[0] - 0.15).abs() < 1e-3, "{rows:?}");
+        assert!((rows[1] - 0.55).abs() < 1e-3, "{rows:?}");
+    }
+
+    #[test]
+    fn patterns_can_be_read_with_their_whitelist() {
+        for search in SEARCH_PARAMS {
+            let SearchParam::Text {
+                kind,
+                patterns,
+                whitelist,
+                ..
+            } = search
+            else {
+                continue;
+            };
+            let whitelist = whitelist.unwrap_or_else(|| panic!("{kind:?} reads any character"));
+            for pattern in *patterns {
+                assert!(
+                    pattern.chars().all(|c| c == ' ' || whitelist.contains(c)),
+                    "{kind:?} pattern {pattern:?} isn't read with its whitelist"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unsure_words_are_rejected() {
+        let mut words = words(&["BUCKLE UP"]);
+        words[1].confidence = 20.;
+        assert!(match_words(&words, &["buckle up"], [0, 0, 1000, 100], 60.).is_none());
+    }
+}
@@ -0,0 +1,359 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::info;
+
+use crate::process::{
+    write_montage, Detection, EventKind, MontageSource, DEFAULT_MULTI_KILL_WINDOW,
+};
+
+pub const PROJECT_EXTENSION: &str = "eclipproj";
+/// bump whenever the format changes in a way older versions can't read
+pub const PROJECT_VERSION: u32 = 1;
+
+/// Saved edit session, stored as json in a `.eclipproj` file
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectFile {
+    pub version: u32,
+    /// recordings the clips are cut from, paths next to the project file are stored relative to it
+    pub inputs: Vec<ProjectInput>,
+    /// clips in the order they are exported
+    pub clips: Vec<ProjectClip>,
+    #[serde(default)]
+    pub export: ExportSettings,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectInput {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub keyframes: Box<[f64]>,
+    #[serde(default)]
+    pub duration: Option<f64>,
+    /// raw detections, clips can be derived again from them with different settings
+    #[serde(default)]
+    pub events: Box<[Detection]>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectClip {
+    /// index into [`ProjectFile::inputs`]
+    pub source: usize,
+    pub start: f64,
+    pub end: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSettings {
+    /// last export path
+    pub output: Option<PathBuf>,
+    pub include_assists: bool,
+    pub include_spectating: bool,
+    #[serde(default)]
+    pub include_deaths: bool,
+    pub elim_clip_duration: f64,
+    #[serde(default)]
+    pub pre_roll: Vec<(EventKind, f64)>,
+    #[serde(default)]
+    pub post_roll: Vec<(EventKind, f64)>,
+    #[serde(default = "default_multi_kill_window")]
+    pub multi_kill_window: f64,
+    #[serde(default)]
+    pub multi_kills_only: bool,
+}
+
+fn default_multi_kill_window() -> f64 {
+    DEFAULT_MULTI_KILL_WINDOW
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        ExportSettings {
+            output: None,
+            include_assists: false,
+            include_spectating: false,
+            include_deaths: false,
+            elim_clip_duration: 4.,
+            pre_roll: Vec::new(),
+            post_roll: Vec::new(),
+            multi_kill_window: DEFAULT_MULTI_KILL_WINDOW,
+            multi_kills_only: false,
+        }
+    }
+}
+
+impl ProjectFile {
+    pub fn new(inputs: Vec<ProjectInput>, clips: Vec<ProjectClip>, export: ExportSettings) -> Self {
+        ProjectFile {
+            version: PROJECT_VERSION,
+            inputs,
+            clips,
+            export,
+        }
+    }
+
+    /// Reads a project file, relative input paths are resolved against its directory
+    pub fn open(path: &Path) -> Result<ProjectFile, ProjectFileError> {
+        let data = std::fs::read(path)?;
+        let mut project: ProjectFile = serde_json::from_slice(&data)?;
+        if project.version > PROJECT_VERSION {
+            return Err(ProjectFileError::UnsupportedVersion(project.version));
+        }
+        if let Some(index) = project
+            .clips
+            .iter()
+            .find(|clip| clip.source >= project.inputs.len())
+            .map(|clip| clip.source)
+        {
+            return Err(ProjectFileError::InvalidSource(index));
+        }
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for input in project.inputs.iter_mut() {
+            if input.path.is_relative() {
+                input.path = dir.join(&input.path);
+            }
+        }
+        project.version = PROJECT_VERSION;
+        Ok(project)
+    }
+
+    /// Writes the project, inputs inside the directory of `path` are stored relative to it so
+    /// the folder can be moved or shared
+    pub fn save(&self, path: &Path) -> Result<(), ProjectFileError> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let dir = std::fs::canonicalize(dir).unwrap_or(dir.to_path_buf());
+        let mut project = self.clone();
+        for input in project.inputs.iter_mut() {
+            let absolute = std::fs::canonicalize(&input.path).unwrap_or(input.path.clone());
+            if let Ok(relative) = absolute.strip_prefix(&dir) {
+                input.path = relative.to_path_buf();
+            }
+        }
+
+        let json = serde_json::to_vec_pretty(&project)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Writes all clips in order to `output`. Consecutive clips of the same input are written
+    /// as one montage source, so inputs can be interleaved
+    pub fn export(&self, output: &Path) -> Result<(), ProjectFileError> {
+        if self.clips.is_empty() {
+            return Err(ProjectFileError::NoClips);
+        }
+        info!(
+            "exporting {} clip(s) from {} input(s)",
+            self.clips.len(),
+            self.inputs.len()
+        );
+
+        let mut runs: Vec<(usize, Vec<[f64; 2]>)> = Vec::new();
+        for clip in self.clips.iter() {
+            match runs.last_mut() {
+                Some((source, clips)) if *source == clip.source => {
+                    clips.push([clip.start, clip.end])
+                }
+                _ => runs.push((clip.source, vec![[clip.start, clip.end]])),
+            }
+        }
+        let sources: Vec<MontageSource> = runs
+            .iter()
+            .map(|(source, clips)| {
+                let input = &self.inputs[*source];
+                MontageSource {
+                    input: &input.path,
+                    clips,
+                    keyframes: &input.keyframes,
+                }
+            })
+            .collect();
+        write_montage(&sources, &output.to_path_buf())?;
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ProjectFileError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid project file: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("project file version {0} is newer than this version supports")]
+    UnsupportedVersion(u32),
+    #[error("clip refers to input {0} which doesn't exist")]
+    InvalidSource(usize),
+    #[error("project has no inputs")]
+    NoInputs,
+    #[error("project has {0} inputs, only projects of a single recording can be opened")]
+    MultipleInputs(usize),
+    #[error("project has no clips")]
+    NoClips,
+    #[error("failed to write clips: {0}")]
+    Ffmpeg(#[from] ffmpeg_next::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_video::temp_path;
+
+    fn project_dir(name: &str) -> PathBuf {
+        let dir = temp_path(name);
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::canonicalize(dir).unwrap()
+    }
+
+    fn input(path: PathBuf) -> ProjectInput {
+        ProjectInput {
+            path,
+            keyframes: Box::new([0., 2., 4.]),
+            duration: Some(5.),
+            events: Box::new([Detection {
+                time: 1.5,
+                kind: EventKind::Elimination,
+                spectating: false,
+                states: Vec::new(),
+                ocr: None,
+                count: 2,
+                rows: vec![0.25, 0.75],
+            }]),
+        }
+    }
+
+    #[test]
+    fn saved_projects_open_unchanged() {
+        let dir = project_dir("round_trip");
+        let recording = dir.join("recording.mp4");
+        std::fs::write(&recording, []).unwrap();
+        let export = ExportSettings {
+            output: Some(dir.join("montage.mp4")),
+            include_assists: true,
+            post_roll: vec![(EventKind::Elimination, 2.)],
+            multi_kills_only: true,
+            ..ExportSettings::default()
+        };
+        let project = ProjectFile::new(
+            vec![input(recording.clone())],
+            vec![ProjectClip {
+                source: 0,
+                start: 0.5,
+                end: 3.5,
+            }],
+            export,
+        );
+
+        let path = dir.join("project.eclipproj");
+        project.save(&path).unwrap();
+        let opened = ProjectFile::open(&path).unwrap();
+
+        assert_eq!(opened.version, PROJECT_VERSION);
+        assert_eq!(opened.inputs.len(), 1);
+        assert_eq!(opened.inputs[0].path, recording);
+        assert_eq!(*opened.inputs[0].keyframes, [0., 2., 4.]);
+        assert_eq!(opened.inputs[0].duration, Some(5.));
+        let event = &opened.inputs[0].events[0];
+        assert_eq!(
+            (event.time, event.kind, event.count),
+            (1.5, EventKind::Elimination, 2)
+        );
+        assert_eq!(event.rows, [0.25, 0.75]);
+        assert_eq!(opened.clips.len(), 1);
+        assert_eq!((opened.clips[0].start, opened.clips[0].end), (0.5, 3.5));
+        assert_eq!(opened.export.output, Some(dir.join("montage.mp4")));
+        assert!(opened.export.include_assists && opened.export.multi_kills_only);
+        assert_eq!(opened.export.post_roll, [(EventKind::Elimination, 2.)]);
+    }
+
+    #[test]
+    fn inputs_next_to_the_project_are_stored_relative_to_it() {
+        let dir = project_dir("relative");
+        std::fs::create_dir(dir.join("recordings")).unwrap();
+        let inside = dir.join("recordings").join("inside.mp4");
+        std::fs::write(&inside, []).unwrap();
+        let outside_dir = project_dir("relative_outside");
+        let outside = outside_dir.join("outside.mp4");
+        std::fs::write(&outside, []).unwrap();
+
+        let path = dir.join("project.eclipproj");
+        ProjectFile::new(
+            vec![input(inside.clone()), input(outside.clone())],
+            Vec::new(),
+            ExportSettings::default(),
+        )
+        .save(&path)
+        .unwrap();
+
+        let stored: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(
+            PathBuf::from(stored["inputs"][0]["path"].as_str().unwrap()),
+            Path::new("recordings").join("inside.mp4")
+        );
+        assert_eq!(
+            PathBuf::from(stored["inputs"][1]["path"].as_str().unwrap()),
+            outside
+        );
+
+        // the folder can be moved, relative inputs move with it
+        let moved = project_dir("relative_moved");
+        std::fs::remove_dir(&moved).unwrap();
+        std::fs::rename(&dir, &moved).unwrap();
+        let opened = ProjectFile::open(&moved.join("project.eclipproj")).unwrap();
+        assert_eq!(
+            opened.inputs[0].path,
+            moved.join("recordings").join("inside.mp4")
+        );
+        assert_eq!(opened.inputs[1].path, outside);
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let dir = project_dir("version");
+        let path = dir.join("project.eclipproj");
+        let mut project = ProjectFile::new(
+            vec![input(dir.join("recording.mp4"))],
+            Vec::new(),
+            ExportSettings::default(),
+        );
+
+        project.version = PROJECT_VERSION + 1;
+        project.save(&path).unwrap();
+        assert!(matches!(
+            ProjectFile::open(&path),
+            Err(ProjectFileError::UnsupportedVersion(version)) if version == PROJECT_VERSION + 1
+        ));
+
+        // older files are read and upgraded
+        project.version = 0;
+        project.save(&path).unwrap();
+        assert_eq!(ProjectFile::open(&path).unwrap().version, PROJECT_VERSION);
+    }
+
+    #[test]
+    fn clips_of_missing_inputs_are_rejected() {
+        let dir = project_dir("invalid_source");
+        let path = dir.join("project.eclipproj");
+        ProjectFile::new(
+            vec![input(dir.join("recording.mp4"))],
+            vec![ProjectClip {
+                source: 1,
+                start: 0.,
+                end: 1.,
+            }],
+            ExportSettings::default(),
+        )
+        .save(&path)
+        .unwrap();
+        assert!(matches!(
+            ProjectFile::open(&path),
+            Err(ProjectFileError::InvalidSource(1))
+        ));
+    }
+}
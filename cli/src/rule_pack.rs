@@ -0,0 +1,105 @@
+use crate::ocr::Word;
+use crate::process::{EventKind, HudState, Resize, SearchArea, SearchParam};
+
+/// tesseract traineddata of the game language, has to be installed in the tessdata directory.
+/// The keywords were only checked against recordings of the English version of the game
+pub const TESSERACT_LANGUAGE: &str = "eng";
+
+/// whitelist of the single word texts, tesseract reads digits and symbols in them as letters
+pub const LETTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+/// whitelist of the announcer subtitles and notifications, words are separated without a
+/// whitelisted space
+pub const SENTENCE_LETTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz'";
+
+/// Whether the ocr `text` contains one of `patterns`, which are lowercase
+pub fn matches(text: &str, patterns: &[&str]) -> bool {
+    let text = text.to_lowercase();
+    patterns.iter().any(|pat| text.contains(pat))
+}
+
+/// The words containing one of `patterns`, in reading order. Patterns of several words are
+/// matched against whole lines, a line matching one of them counts once
+pub fn find_all(words: &[Word], patterns: &[&str]) -> Vec<Word> {
+    let (phrases, keywords): (Vec<&str>, Vec<&str>) =
+        patterns.iter().partition(|pattern| pattern.contains(' '));
+    let mut found = Vec::new();
+    for line in words.chunk_by(|a, b| a.line == b.line) {
+        let joined = Word::join(line, " ");
+        if matches(&joined.text, &phrases) {
+            found.push(joined);
+            continue;
+        }
+        found.extend(
+            line.iter()
+                .filter(|word| matches(&word.text, &keywords))
+                .cloned(),
+        );
+    }
+    found
+}
+
+const KILL_FEED_AREA: SearchArea = SearchArea {
+    top: 0.604,
+    left: 0.2,
+    width: 0.42,
+    height: 0.091,
+};
+
+const WIN_AREA: SearchArea = SearchArea {
+    top: 0.4,
+    left: 0.17,
+    width: 0.69,
+    height: 0.2,
+};
+
+/// Params matched against reference images, checked before the text params. They're skipped unless
+/// their image is in the templates directory
+pub static TEMPLATE_PARAMS: &[SearchParam] = &[
+    SearchParam::Template {
+        kind: EventKind::Elimination,
+        template: "elimination.png",
+        threshold: 0.8,
+        timeout: 0.,
+        clip_length_before: 4.0,
+        clip_length_after: 0.0,
+        search_area: KILL_FEED_AREA,
+        binarisation_params: None,
+        resize: Some(Resize { height: 720 }),
+    },
+    SearchParam::Template {
+        kind: EventKind::Win,
+        template: "win.png",
+        threshold: 0.8,
+        timeout: 30.,
+        clip_length_before: 10.,
+        clip_length_after: 10.,
+        search_area: WIN_AREA,
+        binarisation_params: None,
+        resize: Some(Resize { height: 360 }),
+    },
+];
+
+/// Language independent hud states, checked on every searched frame
+pub static HUD_STATES: &[SearchParam] = &[SearchParam::ColorRegion {
+    state: HudState::Spectating,
+    // the red bar along the bottom edge
+    search_area: SearchArea {
+        top: 0.999,
+        left: 0.,
+        width: 1.,
+        height: 0.001,
+    },
+    min_rgb: [174, 5, 51],
+    max_rgb: [204, 44, 75],
+    min_fraction: 0.6,
+}];
+
+/// Events detected from the hud states of consecutive searched frames
+pub static STATE_EVENTS: &[SearchParam] = &[SearchParam::StateEntered {
+    kind: EventKind::Death,
+    state: HudState::Spectating,
+    timeout: 0.,
+    // the bar only appears after the death cam
+    clip_length_before: 10.,
+    clip_length_after: 2.,
+}];
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use tracing::{info, warn};
+
+use crate::ffmpeg;
+use crate::process::{convert_frame_to_rgb24, BinarisationParams};
+use crate::process_frame::frame_binarisation;
+use crate::video_decoder::VideoDecoder;
+
+/// A reference image of a [`SearchParam::Template`](crate::SearchParam::Template),
+/// preprocessed like the frames it's searched in
+pub struct Template {
+    width: usize,
+    height: usize,
+    /// grayscale with the mean subtracted
+    pixels: Vec<f32>,
+    /// sum of the squared pixels
+    energy: f32,
+}
+
+impl Template {
+    /// Reads an image with ffmpeg. It has to be cut from a frame scaled to the `resize` height of
+    /// its search param, or from a full frame if the param doesn't resize
+    pub fn open(
+        path: &Path,
+        binarisation: Option<&BinarisationParams>,
+    ) -> Result<Template, ffmpeg::Error> {
+        let mut decoder = VideoDecoder::open(&path, false)?;
+        let mut frame = decoder.decode_frame()?;
+        let mut rgb_scaler = decoder.rgb_scaler()?;
+        let mut rgb = convert_frame_to_rgb24(&mut frame, &mut rgb_scaler, decoder.initial_format())
+            .map_err(ffmpeg::Error::from)?;
+        if let Some(params) = binarisation {
+            frame_binarisation(&mut rgb, params.min_rgb, params.max_rgb);
+        }
+
+        let gray: Vec<f32> = rgb.chunks_exact(3).map(luma).collect();
+        let mean = gray.iter().sum::<f32>() / gray.len().max(1) as f32;
+        let pixels: Vec<f32> = gray.iter().map(|v| v - mean).collect();
+        let energy = pixels.iter().map(|v| v * v).sum();
+        Ok(Template {
+            width: frame.width() as usize,
+            height: frame.height() as usize,
+            pixels,
+            energy,
+        })
+    }
+
+    /// Normalized cross-correlations from -1 to 1 of the non-overlapping positions in `area`
+    /// (left, top, width, height) of an rgb24 image where the template scores at least
+    /// `threshold`, best first. A kill feed icon matches once per entry
+    pub fn matches(
+        &self,
+        rgb: &[u8],
+        image_width: usize,
+        area: [usize; 4],
+        threshold: f32,
+    ) -> Vec<f32> {
+        let [left, top, width, height] = area;
+        // a template without contrast would match everything
+        if width < self.width || height < self.height || self.energy == 0. {
+            return Vec::new();
+        }
+
+        let gray: Vec<f32> = (top..top + height)
+            .flat_map(|y| {
+                let start = (y * image_width + left) * 3;
+                rgb[start..start + width * 3].chunks_exact(3).map(luma)
+            })
+            .collect();
+
+        // integral images of the area and its square, for the mean and variance under the template
+        let stride = width + 1;
+        let mut sum = vec![0_f64; stride * (height + 1)];
+        let mut squared = vec![0_f64; stride * (height + 1)];
+        for y in 0..height {
+            for x in 0..width {
+                let v = gray[y * width + x] as f64;
+                let i = (y + 1) * stride + x + 1;
+                sum[i] = v + sum[i - stride] + sum[i - 1] - sum[i - stride - 1];
+                squared[i] = v * v + squared[i - stride] + squared[i - 1] - squared[i - stride - 1];
+            }
+        }
+        let window = |table: &[f64], x: usize, y: usize| {
+            table[(y + self.height) * stride + x + self.width]
+                - table[y * stride + x + self.width]
+                - table[(y + self.height) * stride + x]
+                + table[y * stride + x]
+        };
+        let n = (self.width * self.height) as f64;
+
+        let mut candidates = Vec::new();
+        for y in 0..=height - self.height {
+            for x in 0..=width - self.width {
+                let window_sum = window(&sum, x, y);
+                let variance = window(&squared, x, y) - window_sum * window_sum / n;
+                if variance < 1. {
+                    continue;
+                }
+                // the template has zero mean, so the mean of the window cancels out
+                let cross: f32 = (0..self.height)
+                    .map(|ty| {
+                        let row = &gray[(y + ty) * width + x..][..self.width];
+                        let template_row = &self.pixels[ty * self.width..][..self.width];
+                        row.iter()
+                            .zip(template_row)
+                            .map(|(a, b)| a * b)
+                            .sum::<f32>()
+                    })
+                    .sum();
+                let ncc = (cross as f64 / (variance * self.energy as f64).sqrt()) as f32;
+                if ncc >= threshold {
+                    candidates.push((x, y, ncc));
+                }
+            }
+        }
+
+        // the positions around a match score almost as high, only the best one of them is kept
+        candidates.sort_by(|a, b| b.2.total_cmp(&a.2));
+        let mut found: Vec<(usize, usize, f32)> = Vec::new();
+        for (x, y, ncc) in candidates {
+            if found
+                .iter()
+                .all(|(fx, fy, _)| x.abs_diff(*fx) >= self.width || y.abs_diff(*fy) >= self.height)
+            {
+                found.push((x, y, ncc));
+            }
+        }
+        found.into_iter().map(|(_, _, ncc)| ncc).collect()
+    }
+}
+
+fn luma(rgb: &[u8]) -> f32 {
+    0.299 * rgb[0] as f32 + 0.587 * rgb[1] as f32 + 0.114 * rgb[2] as f32
+}
+
+/// The templates of a directory, each loaded the first time it's searched for
+pub struct Templates {
+    dir: Option<PathBuf>,
+    loaded: HashMap<&'static str, Option<Template>>,
+}
+
+impl Templates {
+    pub fn new(dir: Option<PathBuf>) -> Templates {
+        Templates {
+            dir,
+            loaded: HashMap::new(),
+        }
+    }
+
+    /// `None` without a templates directory or if the image can't be read, which is only logged once
+    pub fn get(
+        &mut self,
+        name: &'static str,
+        binarisation: Option<&BinarisationParams>,
+    ) -> Option<&Template> {
+        let dir = self.dir.as_ref()?;
+        self.loaded
+            .entry(name)
+            .or_insert_with(|| {
+                let path = dir.join(name);
+                match Template::open(&path, binarisation) {
+                    Ok(template) => {
+                        info!("loaded template {}", path.display());
+                        Some(template)
+                    }
+                    Err(err) => {
+                        warn!("failed to load template {}: {err}", path.display());
+                        None
+                    }
+                }
+            })
+            .as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_image(path: &Path) -> (Vec<u8>, usize, usize) {
+        let mut decoder = VideoDecoder::open(&path, false).unwrap();
+        let mut frame = decoder.decode_frame().unwrap();
+        let mut rgb_scaler = decoder.rgb_scaler().unwrap();
+        let rgb =
+            convert_frame_to_rgb24(&mut frame, &mut rgb_scaler, decoder.initial_format()).unwrap();
+        (rgb, frame.width() as usize, frame.height() as usize)
+    }
+
+    #[test]
+    fn shipped_elimination_template_matches_a_kill_feed() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let template = Template::open(
+            &manifest_dir.join("../src-tauri/templates/elimination.png"),
+            None,
+        )
+        .unwrap();
+        // the kill feed area of screenshot3 scaled to 720p, the template was cut from screenshot2
+        let (rgb, width, height) = test_image(&manifest_dir.join("test_data/kill_feed.png"));
+
+        let matches = template.matches(&rgb, width, [0, 0, width, height], 0.8);
+        assert_eq!(matches.len(), 1, "{matches:?}");
+        assert!(matches[0] < 0.99, "{matches:?}");
+
+        // the right half only holds the player name and the weapon
+        assert!(template
+            .matches(&rgb, width, [width * 3 / 4, 0, width / 4, height], 0.8)
+            .is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+//! Short generated recordings for the tests that need a video file
+
+use std::path::{Path, PathBuf};
+
+use ffmpeg_next::{codec, encoder, format, frame, ChannelLayout, Packet, Rational};
+
+use crate::ffmpeg;
+
+pub const WIDTH: u32 = 64;
+pub const HEIGHT: u32 = 64;
+pub const FRAME_RATE: i32 = 10;
+/// frames per keyframe interval
+pub const GOP: u32 = 10;
+const SAMPLE_RATE: i32 = 48000;
+
+/// A fresh path in the temp directory, removed before it's handed out
+pub fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "the-finals-eclipper-test-{}-{name}",
+        std::process::id()
+    ));
+    std::fs::remove_file(&path).ok();
+    path
+}
+
+/// Writes `frames` frames at [`FRAME_RATE`] fps with a keyframe every [`GOP`] frames. Frame `n`
+/// is a flat gray of `luma(n)` so decoded frames can be told apart. Each of the `audio_tracks`
+/// is a tone of its own frequency
+pub fn write_video(
+    path: &Path,
+    frames: usize,
+    luma: impl Fn(usize) -> u8,
+    audio_tracks: usize,
+) -> Result<(), ffmpeg::Error> {
+    ffmpeg::init()?;
+    let mut octx = format::output(&path)?;
+    let global_header = octx.format().flags().contains(format::Flags::GLOBAL_HEADER);
+    let time_base = Rational::new(1, FRAME_RATE);
+
+    let codec = encoder::find(codec::Id::MPEG4).ok_or(ffmpeg::Error::EncoderNotFound)?;
+    let mut ost = octx.add_stream(codec)?;
+    let mut video = codec::context::Context::new_with_codec(codec)
+        .encoder()
+        .video()?;
+    video.set_width(WIDTH);
+    video.set_height(HEIGHT);
+    video.set_format(format::Pixel::YUV420P);
+    video.set_frame_rate(Some(Rational::new(FRAME_RATE, 1)));
+    video.set_time_base(time_base);
+    video.set_gop(GOP);
+    video.set_max_b_frames(0);
+    if global_header {
+        video.set_flags(codec::Flags::GLOBAL_HEADER);
+    }
+    let mut video = video.open_as(codec)?;
+    ost.set_parameters(&video);
+    ost.set_time_base(time_base);
+
+    let mut audio = Vec::new();
+    for _ in 0..audio_tracks {
+        let codec = encoder::find(codec::Id::AAC)
+            .ok_or(ffmpeg::Error::EncoderNotFound)?
+            .audio()?;
+        let mut ost = octx.add_stream(codec)?;
+        let mut encoder = codec::context::Context::new_with_codec(*codec)
+            .encoder()
+            .audio()?;
+        encoder.set_rate(SAMPLE_RATE);
+        encoder.set_channel_layout(ChannelLayout::STEREO);
+        encoder.set_format(format::Sample::F32(format::sample::Type::Planar));
+        encoder.set_time_base((1, SAMPLE_RATE));
+        if global_header {
+            encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        let encoder = encoder.open_as(codec)?;
+        ost.set_parameters(&encoder);
+        ost.set_time_base((1, SAMPLE_RATE));
+        audio.push((ost.index(), encoder, 0_i64));
+    }
+
+    octx.write_header()?;
+
+    let mut picture = frame::Video::new(format::Pixel::YUV420P, WIDTH, HEIGHT);
+    for n in 0..frames {
+        picture.data_mut(0).fill(luma(n));
+        picture.data_mut(1).fill(128);
+        picture.data_mut(2).fill(128);
+        picture.set_pts(Some(n as i64));
+        video.send_frame(&picture)?;
+        write_packets(&mut video, 0, time_base, &mut octx)?;
+
+        // audio up to the end of this frame
+        let until = (n as i64 + 1) * SAMPLE_RATE as i64 / FRAME_RATE as i64;
+        for (track, (index, encoder, samples)) in audio.iter_mut().enumerate() {
+            while *samples < until {
+                let size = encoder.frame_size() as usize;
+                let mut chunk = frame::Audio::new(encoder.format(), size, ChannelLayout::STEREO);
+                chunk.set_rate(SAMPLE_RATE as u32);
+                let frequency = 440. * (track + 1) as f32;
+                for channel in 0..2 {
+                    for (i, sample) in chunk.plane_mut::<f32>(channel).iter_mut().enumerate() {
+                        let t = (*samples + i as i64) as f32 / SAMPLE_RATE as f32;
+                        *sample = 0.2 * (t * frequency * std::f32::consts::TAU).sin();
+                    }
+                }
+                chunk.set_pts(Some(*samples));
+                *samples += size as i64;
+                encoder.send_frame(&chunk)?;
+                write_packets(encoder, *index, Rational::new(1, SAMPLE_RATE), &mut octx)?;
+            }
+        }
+    }
+
+    video.send_eof()?;
+    write_packets(&mut video, 0, time_base, &mut octx)?;
+    for (index, encoder, _) in audio.iter_mut() {
+        encoder.send_eof()?;
+        write_packets(encoder, *index, Rational::new(1, SAMPLE_RATE), &mut octx)?;
+    }
+    octx.write_trailer()
+}
+
+fn write_packets(
+    encoder: &mut encoder::Encoder,
+    stream_index: usize,
+    time_base: Rational,
+    octx: &mut format::context::Output,
+) -> Result<(), ffmpeg::Error> {
+    let ost_time_base = octx.stream(stream_index).unwrap().time_base();
+    let mut encoded = Packet::empty();
+    while encoder.receive_packet(&mut encoded).is_ok() {
+        encoded.set_stream(stream_index);
+        encoded.rescale_ts(time_base, ost_time_base);
+        encoded.write_interleaved(octx)?;
+    }
+    Ok(())
+}
+
+/// The average luma of a decoded frame, close to the `luma` it was written with
+pub fn frame_luma(frame: &frame::Video) -> u8 {
+    let width = frame.width() as usize;
+    let stride = frame.stride(0);
+    let rows = frame.data(0).chunks(stride).take(frame.height() as usize);
+    let sum: usize = rows
+        .map(|row| row[..width].iter().map(|v| *v as usize).sum::<usize>())
+        .sum();
+    (sum / (width * frame.height() as usize)) as u8
+}
@@ -3,11 +3,14 @@ use std::io::{Cursor, Seek, SeekFrom, Write};
 use std::os::raw::c_int;
 use std::path::PathBuf;
 use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 use ffmpeg::format::Pixel;
 use ffmpeg::frame::Video;
 use ffmpeg::software::scaling;
-use ffmpeg::{codec, format, media, Rational};
+use ffmpeg::{codec, format, media, Dictionary, Discard, Rational};
 use ffmpeg_next as ffmpeg;
 use ffmpeg_next::sys::AVHWDeviceType::{
     AV_HWDEVICE_TYPE_CUDA, AV_HWDEVICE_TYPE_DXVA2, AV_HWDEVICE_TYPE_NONE,
@@ -15,19 +18,49 @@ use ffmpeg_next::sys::AVHWDeviceType::{
 use ffmpeg_next::sys::{
     av_buffer_ref, av_guess_format, av_hwdevice_ctx_create, av_hwdevice_iterate_types, av_malloc,
     av_seek_frame, avcodec_get_hw_config, avformat_alloc_output_context2, avformat_flush,
-    avio_alloc_context, AVCodecContext, AVHWDeviceType, AVPixelFormat,
-    AV_CODEC_HW_CONFIG_METHOD_HW_DEVICE_CTX, AV_NOPTS_VALUE, AV_TIME_BASE,
+    avio_alloc_context, AVCodecContext, AVHWDeviceType, AVPixelFormat, AVERROR_EOF,
+    AVSEEK_FLAG_BACKWARD, AV_CODEC_HW_CONFIG_METHOD_HW_DEVICE_CTX, AV_NOPTS_VALUE, AV_TIME_BASE,
 };
-use ffmpeg_next::{encoder, Error};
-use tracing::info;
+use ffmpeg_next::{encoder, Error, Packet};
+use tracing::{error, info};
+
+use crate::channel::{bounded, Receiver, Sender, TrySendError};
+use crate::live::{is_network_url, network_options, RollingBuffer};
+use crate::process::{convert_frame_to_rgb24, PreviewFrame};
+
+/// how often a followed recording is checked for new data
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// video packets of a recorded stream waiting to be decoded, about a minute at 60 fps
+const RECORD_QUEUE_SIZE: usize = 4000;
 
 pub struct VideoDecoder {
     #[allow(dead_code)]
     path: PathBuf,
-    input_ctx: format::context::Input,
+    /// `None` once a recorded stream is read on its own thread, see [`VideoDecoder::record`]
+    input_ctx: Option<format::context::Input>,
     decoder: codec::decoder::Video,
     video_stream_index: usize,
+    time_base: Rational,
+    frame_rate: Rational,
     initial_format: Pixel,
+    /// set while the input is still being recorded, see [`VideoDecoder::follow`]
+    follow: Option<Follow>,
+    /// the input is a network stream, read errors end it instead of being skipped
+    live: bool,
+    /// video packets read by the thread of a recorded stream
+    packets: Option<Receiver<Packet>>,
+    reader: Option<JoinHandle<()>>,
+}
+
+struct Follow {
+    /// stop waiting for new data after the file hasn't grown for this long
+    idle_timeout: Duration,
+    /// file size when the input was last opened
+    size: u64,
+    /// byte position of the last video packet read
+    position: u64,
+    /// pts of the last returned frame, frames up to it are skipped after reopening
+    last_pts: Option<i64>,
 }
 
 impl VideoDecoder {
@@ -35,7 +68,12 @@ impl VideoDecoder {
         path: &P,
         hw_accel: bool,
     ) -> Result<VideoDecoder, Error> {
-        let input_ctx = format::input(path)?;
+        let live = is_network_url(path.as_ref());
+        let input_ctx = if live {
+            format::input_with_dictionary(path, network_options(&path.as_ref().to_string_lossy()))?
+        } else {
+            format::input(path)?
+        };
 
         let video_stream = input_ctx
             .streams()
@@ -43,6 +81,8 @@ impl VideoDecoder {
             .ok_or(Error::StreamNotFound)?;
 
         let video_stream_index = video_stream.index();
+        let time_base = video_stream.time_base();
+        let frame_rate = video_stream.rate();
 
         let mut decoder_ctx = codec::context::Context::from_parameters(video_stream.parameters())?;
 
@@ -71,42 +111,178 @@ impl VideoDecoder {
 
         Ok(VideoDecoder {
             path: path.as_ref().to_path_buf(),
-            input_ctx,
+            input_ctx: Some(input_ctx),
             decoder,
             video_stream_index,
+            time_base,
+            frame_rate,
             initial_format,
+            follow: None,
+            live,
+            packets: None,
+            reader: None,
         })
     }
 
+    /// Reads the input on its own thread and copies every packet into `buffer`, so a live stream
+    /// can be written out later. The stream is read as fast as it is received even if decoding
+    /// falls behind, which would make the network input drop packets. The input can't be
+    /// seeked or remuxed afterwards
+    pub fn record(&mut self, buffer: Arc<Mutex<RollingBuffer>>) {
+        let Some(input_ctx) = self.input_ctx.take() else {
+            return;
+        };
+        let (tx, rx) = bounded(RECORD_QUEUE_SIZE);
+        let video_stream_index = self.video_stream_index;
+        let live = self.live;
+        self.reader = Some(std::thread::spawn(move || {
+            read_recorded(input_ctx, video_stream_index, live, buffer, tx)
+        }));
+        self.packets = Some(rx);
+    }
+
+    fn input(&self) -> &format::context::Input {
+        self.input_ctx
+            .as_ref()
+            .expect("the input of a recorded stream is read on its own thread")
+    }
+
+    fn input_mut(&mut self) -> &mut format::context::Input {
+        self.input_ctx
+            .as_mut()
+            .expect("the input of a recorded stream is read on its own thread")
+    }
+
+    /// Keeps decoding while the input is still being written, e.g. an mkv or fragmented mp4
+    /// that is being recorded. On EOF the decoder waits for the file to grow, reopens it and
+    /// continues after the last returned frame. Decoding ends once the file hasn't grown for
+    /// `idle_timeout`
+    pub fn follow(&mut self, idle_timeout: Duration) {
+        self.follow = Some(Follow {
+            idle_timeout,
+            size: std::fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0),
+            position: 0,
+            last_pts: None,
+        });
+    }
+
+    /// how much of the data recorded so far has been read in percent, only set when following
+    pub fn follow_progress(&self) -> Option<f32> {
+        let follow = self.follow.as_ref()?;
+        Some((follow.position as f64 / follow.size.max(1) as f64 * 100.).min(100.) as f32)
+    }
+
     pub fn initial_format(&self) -> Pixel {
         self.initial_format
     }
 
+    /// only decode keyframes, every other frame is discarded by the decoder
+    pub fn set_keyframes_only(&mut self, keyframes_only: bool) {
+        self.decoder.skip_frame(if keyframes_only {
+            Discard::NonKey
+        } else {
+            Discard::Default
+        });
+    }
+
     pub fn decode_frame(&mut self) -> Result<Video, Error> {
         let mut decoded = Video::empty();
-        for (stream, packet) in self.input_ctx.packets() {
-            if stream.index() == self.video_stream_index {
-                if self.decoder.receive_frame(&mut decoded).is_ok() {
-                    return Ok(decoded);
+        loop {
+            while let Some(packet) = self.read_packet() {
+                if packet.stream() == self.video_stream_index {
+                    if self.decoder.receive_frame(&mut decoded).is_ok()
+                        && is_new_frame(&mut self.follow, &decoded)
+                    {
+                        return Ok(decoded);
+                    }
+
+                    match &mut self.follow {
+                        Some(follow) => {
+                            if let Ok(position) = u64::try_from(packet.position()) {
+                                follow.position = position;
+                            }
+                            // the last packet before EOF may only be partially written
+                            self.decoder.send_packet(&packet).ok();
+                        }
+                        None => self.decoder.send_packet(&packet)?,
+                    }
+
+                    if self.decoder.receive_frame(&mut decoded).is_ok()
+                        && is_new_frame(&mut self.follow, &decoded)
+                    {
+                        return Ok(decoded);
+                    }
                 }
+            }
 
-                self.decoder.send_packet(&packet)?;
-
-                if self.decoder.receive_frame(&mut decoded).is_ok() {
-                    return Ok(decoded);
-                }
+            if !self.wait_for_data()? {
+                break;
             }
         }
 
         self.decoder.send_eof()?;
 
-        if self.decoder.receive_frame(&mut decoded).is_ok() {
-            return Ok(decoded);
+        while self.decoder.receive_frame(&mut decoded).is_ok() {
+            if is_new_frame(&mut self.follow, &decoded) {
+                return Ok(decoded);
+            }
         }
 
         Err(Error::Eof)
     }
 
+    /// Next packet of any stream, `None` at the end of the input. Only video packets are
+    /// returned for a recorded stream
+    fn read_packet(&mut self) -> Option<Packet> {
+        if let Some(packets) = &self.packets {
+            return packets.recv().ok();
+        }
+        let live = self.live;
+        read_packet(self.input_mut(), live)
+    }
+
+    /// Waits until a followed input has grown and reopens it after the last returned frame.
+    /// Returns false if the input isn't followed or hasn't grown within the idle timeout
+    fn wait_for_data(&mut self) -> Result<bool, Error> {
+        let Some(follow) = &mut self.follow else {
+            return Ok(false);
+        };
+
+        let waiting_since = Instant::now();
+        loop {
+            let size = std::fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+            if size > follow.size {
+                follow.size = size;
+                break;
+            }
+            if waiting_since.elapsed() >= follow.idle_timeout {
+                info!("{} stopped growing", self.path.display());
+                return Ok(false);
+            }
+            std::thread::sleep(FOLLOW_POLL_INTERVAL);
+        }
+
+        let input_ctx = self.input_ctx.insert(format::input(&self.path)?);
+        self.video_stream_index = input_ctx
+            .streams()
+            .best(media::Type::Video)
+            .ok_or(Error::StreamNotFound)?
+            .index();
+        if let Some(pts) = follow.last_pts {
+            unsafe {
+                av_seek_frame(
+                    input_ctx.as_mut_ptr(),
+                    self.video_stream_index as c_int,
+                    pts,
+                    AVSEEK_FLAG_BACKWARD,
+                );
+            }
+        }
+        // frames decoded from the truncated end of the file are decoded again after the seek
+        self.decoder.flush();
+        Ok(true)
+    }
+
     pub fn rgb_scaler(&mut self) -> Result<scaling::Context, Error> {
         scaling::Context::get(
             self.decoder.format(),
@@ -132,19 +308,11 @@ impl VideoDecoder {
     }
 
     pub fn frame_rate(&self) -> f64 {
-        let rate = self
-            .input_ctx
-            .stream(self.video_stream_index)
-            .unwrap()
-            .rate();
-        rate.numerator() as f64 / rate.denominator() as f64
+        self.frame_rate.numerator() as f64 / self.frame_rate.denominator() as f64
     }
 
     pub fn time_base(&self) -> Rational {
-        self.input_ctx
-            .stream(self.video_stream_index)
-            .unwrap()
-            .time_base()
+        self.time_base
     }
 
     pub fn seek(&mut self, secs: f64) {
@@ -154,7 +322,7 @@ impl VideoDecoder {
         info!("secs: {secs}, timestamp: {timestamp}");
         unsafe {
             av_seek_frame(
-                self.input_ctx.as_mut_ptr(),
+                self.input_mut().as_mut_ptr(),
                 self.video_stream_index as c_int,
                 timestamp as i64,
                 0,
@@ -162,9 +330,71 @@ impl VideoDecoder {
         };
     }
 
-    // video duration
+    /// Seeks to exactly `secs` and returns the frame shown at that time.
+    ///
+    /// Unlike [`VideoDecoder::seek`] this flushes the decoder, seeks to the preceding keyframe
+    /// and decodes forward until the requested timestamp is reached.
+    pub fn seek_exact(&mut self, secs: f64) -> Result<Video, Error> {
+        let time_base = self.time_base();
+        // rounded, a frame's own time can be a hair below its timestamp after the division
+        let timestamp =
+            (secs / (time_base.numerator() as f64 / time_base.denominator() as f64)).round() as i64;
+
+        let ret = unsafe {
+            av_seek_frame(
+                self.input_mut().as_mut_ptr(),
+                self.video_stream_index as c_int,
+                timestamp,
+                AVSEEK_FLAG_BACKWARD,
+            )
+        };
+        if ret < 0 {
+            return Err(Error::from(ret));
+        }
+        self.decoder.flush();
+
+        let mut previous: Option<Video> = None;
+        loop {
+            let frame = match self.decode_frame() {
+                Ok(frame) => frame,
+                // past the last frame, the requested time is shown by the last decoded frame
+                Err(Error::Eof) => return previous.ok_or(Error::Eof),
+                Err(err) => return Err(err),
+            };
+            let Some(pts) = frame.pts().or(frame.timestamp()) else {
+                continue;
+            };
+            if pts == timestamp {
+                return Ok(frame);
+            }
+            if pts > timestamp {
+                return Ok(previous.unwrap_or(frame));
+            }
+            previous = Some(frame);
+        }
+    }
+
+    /// Decodes the frame shown at `secs` and converts it to RGB24
+    pub fn extract_frame(&mut self, secs: f64) -> Result<PreviewFrame, Error> {
+        let mut frame = self.seek_exact(secs)?;
+        let mut rgb_scaler = self.rgb_scaler()?;
+        let pixels = convert_frame_to_rgb24(&mut frame, &mut rgb_scaler, self.initial_format)
+            .map_err(Error::from)?;
+
+        Ok(PreviewFrame {
+            width: frame.width(),
+            height: frame.height(),
+            pts: frame.pts().unwrap_or_default(),
+            pixels: pixels.into_boxed_slice(),
+        })
+    }
+
+    // video duration, 0 if unknown
     pub fn video_duration(&self) -> f64 {
-        let duration = self.input_ctx.duration();
+        let Some(input_ctx) = &self.input_ctx else {
+            return 0.;
+        };
+        let duration = input_ctx.duration();
 
         if duration == AV_NOPTS_VALUE {
             return 0.;
@@ -175,13 +405,13 @@ impl VideoDecoder {
 
     pub fn key_frames(mut self) -> Box<[f64]> {
         let mut key_frames = vec![];
-        let video = self
-            .input_ctx
+        let input_ctx = self.input_mut();
+        let video = input_ctx
             .streams()
             .best(media::Type::Video)
             .unwrap()
             .index();
-        for (stream, packet) in self.input_ctx.packets() {
+        for (stream, packet) in input_ctx.packets() {
             if stream.index() == video && packet.is_key() {
                 key_frames.push(t_to_secs(packet.pts().unwrap(), stream.time_base()));
             }
@@ -224,14 +454,112 @@ impl VideoDecoder {
             }
         }?;
 
-        let mut stream_mapping = vec![0_isize; self.input_ctx.nb_streams() as _];
+        self.remux_range(
+            &mut octx,
+            start,
+            end,
+            keyframes,
+            &[
+                media::Type::Audio,
+                media::Type::Video,
+                media::Type::Subtitle,
+            ],
+            Dictionary::new(),
+        )?;
+
+        octx.write_trailer()?;
+        unsafe {
+            avformat_flush(octx.as_mut_ptr());
+        }
+
+        unsafe {
+            (*(*octx.as_mut_ptr()).pb).opaque = ptr::null_mut();
+        };
+
+        Ok(output.into())
+    }
+
+    /// Fragmented mp4 of `start..end` written to `writer` while the input is read.
+    ///
+    /// Unlike [`VideoDecoder::transcode_range`] the range is never held in memory, every fragment
+    /// starts on a keyframe and is passed to `writer` as soon as it is complete.
+    /// Only audio and video streams are included.
+    pub fn stream_range<W: Write>(
+        &mut self,
+        start: f64,
+        end: f64,
+        keyframes: &[f64],
+        mut writer: W,
+    ) -> Result<(), Error> {
+        let mut ofmt_ctx = ptr::null_mut();
+        let out_buffer;
+
+        let mut octx = unsafe {
+            out_buffer = av_malloc(32768) as *mut c_uchar;
+            let format_name = CString::new("mp4").unwrap();
+            match avformat_alloc_output_context2(
+                &mut ofmt_ctx,
+                ptr::null(),
+                format_name.as_ptr(),
+                ptr::null(),
+            ) {
+                0 => {
+                    // no seek callback, the output is not seekable
+                    let out_ctx = avio_alloc_context(
+                        out_buffer,
+                        32768,
+                        1,
+                        (&mut writer as *mut W) as *mut c_void,
+                        None,
+                        Some(write_stream::<W>),
+                        None,
+                    );
+                    (*ofmt_ctx).pb = out_ctx;
+                    Ok(format::context::Output::wrap(ofmt_ctx))
+                }
+
+                e => Err(Error::from(e)),
+            }
+        }?;
+
+        let mut header_options = Dictionary::new();
+        header_options.set("movflags", "frag_keyframe+empty_moov+default_base_moof");
+
+        let result = self.remux_range(
+            &mut octx,
+            start,
+            end,
+            keyframes,
+            &[media::Type::Audio, media::Type::Video],
+            header_options,
+        );
+        // the trailer can't be written if the writer stopped accepting data
+        if result.is_ok() {
+            octx.write_trailer()?;
+        }
+        unsafe {
+            avformat_flush(octx.as_mut_ptr());
+            (*(*octx.as_mut_ptr()).pb).opaque = ptr::null_mut();
+        }
+
+        result
+    }
+
+    /// copies the packets of `start..end` to `octx`, which must not have any streams yet
+    fn remux_range(
+        &mut self,
+        octx: &mut format::context::Output,
+        start: f64,
+        end: f64,
+        keyframes: &[f64],
+        media_types: &[media::Type],
+        header_options: Dictionary,
+    ) -> Result<(), Error> {
+        let mut stream_mapping = vec![0_isize; self.input().nb_streams() as _];
         let mut ost_index = 0;
-        for (ist_index, ist) in self.input_ctx.streams().enumerate() {
+        for (ist_index, ist) in self.input().streams().enumerate() {
             let ist_medium = ist.parameters().medium();
-            if ist_medium != media::Type::Audio
-                && ist_medium != media::Type::Video
-                && ist_medium != media::Type::Subtitle
-            {
+            if !media_types.contains(&ist_medium) {
                 stream_mapping[ist_index] = -1;
                 continue;
             }
@@ -247,14 +575,14 @@ impl VideoDecoder {
             }
         }
 
-        octx.set_metadata(self.input_ctx.metadata().to_owned());
-        octx.write_header()?;
+        octx.set_metadata(self.input().metadata().to_owned());
+        octx.write_header_with(header_options)?;
 
         if let Some(keyframe) = keyframes.iter().rev().copied().find(|k| *k < start) {
             self.seek(keyframe);
         }
 
-        for (stream, mut packet) in self.input_ctx.packets() {
+        for (stream, mut packet) in self.input_mut().packets() {
             let ost_index = stream_mapping[packet.stream()];
             if ost_index < 0 {
                 continue;
@@ -277,19 +605,10 @@ impl VideoDecoder {
             packet.rescale_ts(ist_time_base, ost.time_base());
             packet.set_position(-1);
             packet.set_stream(ost_index as _);
-            packet.write_interleaved(&mut octx)?;
-        }
-
-        octx.write_trailer()?;
-        unsafe {
-            avformat_flush(octx.as_mut_ptr());
+            packet.write_interleaved(octx)?;
         }
 
-        unsafe {
-            (*(*octx.as_mut_ptr()).pb).opaque = ptr::null_mut();
-        };
-
-        Ok(output.into())
+        Ok(())
     }
 }
 
@@ -311,6 +630,22 @@ unsafe extern "C" fn seek_buffer(opaque: *mut c_void, offset: i64, whence: c_int
     output.seek(seek_from).unwrap_or_default() as i64
 }
 
+unsafe extern "C" fn write_stream<W: Write>(
+    opaque: *mut c_void,
+    buf: *const u8,
+    buf_size: c_int,
+) -> c_int {
+    let writer: &mut W = &mut *(opaque as *mut W);
+
+    let data = std::slice::from_raw_parts(buf, buf_size as usize);
+
+    match writer.write_all(data) {
+        Ok(()) => buf_size,
+        // the reader went away, abort muxing
+        Err(_) => AVERROR_EOF,
+    }
+}
+
 unsafe extern "C" fn write_buffer(opaque: *mut c_void, buf: *const u8, buf_size: c_int) -> c_int {
     let output: &mut Cursor<&mut Vec<u8>> = &mut *(opaque as *mut Cursor<&mut Vec<u8>>);
 
@@ -319,6 +654,66 @@ unsafe extern "C" fn write_buffer(opaque: *mut c_void, buf: *const u8, buf_size:
     output.write(data).unwrap_or_default() as c_int
 }
 
+/// Next packet of any stream, `None` at the end of the input.
+/// Read errors are skipped like [`format::context::Input::packets`] does, except for live
+/// streams where they mean the sender stopped or timed out
+fn read_packet(input_ctx: &mut format::context::Input, live: bool) -> Option<Packet> {
+    let mut packet = Packet::empty();
+    loop {
+        match packet.read(input_ctx) {
+            Ok(()) => return Some(packet),
+            Err(Error::Eof) => return None,
+            Err(Error::Other { errno }) if errno == ffmpeg::error::EAGAIN => {}
+            Err(err) if live => {
+                info!("stream ended: {err}");
+                return None;
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+/// Copies the packets of a recorded stream into `buffer` and passes its video packets on to
+/// `packets`. When decoding falls too far behind, video packets are left out up to the next
+/// keyframe, the buffer still gets all of them
+fn read_recorded(
+    mut input_ctx: format::context::Input,
+    video_stream_index: usize,
+    live: bool,
+    buffer: Arc<Mutex<RollingBuffer>>,
+    packets: Sender<Packet>,
+) {
+    let mut skipping = false;
+    while let Some(packet) = read_packet(&mut input_ctx, live) {
+        if let Err(err) = buffer.lock().unwrap().write(&input_ctx, &packet) {
+            error!("failed to buffer packet: {err}");
+        }
+        if packet.stream() != video_stream_index || (skipping && !packet.is_key()) {
+            continue;
+        }
+        match packets.try_send(packet) {
+            Ok(()) => skipping = false,
+            Err(TrySendError::Full(_)) => {
+                if !skipping {
+                    info!("decoding fell behind the stream, skipping to the next keyframe");
+                }
+                skipping = true;
+            }
+            Err(TrySendError::Disconnected(_)) => break,
+        }
+    }
+}
+
+impl Drop for VideoDecoder {
+    /// Stops the reader of a recorded stream, its buffer can be used once the decoder is dropped
+    fn drop(&mut self) {
+        self.packets = None;
+        if let Some(reader) = self.reader.take() {
+            reader.join().ok();
+        }
+    }
+}
+
 pub struct VideoDecoderIter<'a> {
     decoder: &'a mut VideoDecoder,
 }
@@ -331,6 +726,22 @@ impl<'a> Iterator for VideoDecoderIter<'a> {
     }
 }
 
+/// Frames are only returned once when following an input, the same frames are decoded again
+/// after reopening
+fn is_new_frame(follow: &mut Option<Follow>, frame: &Video) -> bool {
+    let Some(follow) = follow else {
+        return true;
+    };
+    let Some(pts) = frame.pts() else {
+        return true;
+    };
+    if follow.last_pts.is_some_and(|last| pts <= last) {
+        return false;
+    }
+    follow.last_pts = Some(pts);
+    true
+}
+
 pub fn t_to_secs(t: i64, time_base: Rational) -> f64 {
     let ratio = time_base.numerator() as f64 / time_base.denominator() as f64;
     t as f64 * ratio
@@ -401,3 +812,40 @@ unsafe extern "C" fn get_hw_format(
         AVPixelFormat::AV_PIX_FMT_NONE
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_video::{frame_luma, temp_path, write_video, FRAME_RATE, GOP};
+
+    fn luma(n: usize) -> u8 {
+        20 + n as u8 * 6
+    }
+
+    #[test]
+    fn seek_exact_lands_on_the_requested_frame() {
+        let input = temp_path("seek_exact.mp4");
+        write_video(&input, 30, luma, 0).unwrap();
+        let mut decoder = VideoDecoder::open(&input, false).unwrap();
+
+        // forwards and backwards, on and between frames, none of them a keyframe
+        for (secs, expected) in [(1.5, 15), (2.7, 27), (0.3, 3), (1.55, 15), (1.99, 19)] {
+            assert_ne!(expected % GOP as usize, 0);
+            let frame = decoder.seek_exact(secs).unwrap();
+            let pts = frame.pts().unwrap();
+            let time = t_to_secs(pts, decoder.time_base());
+            assert!(
+                (time - expected as f64 / FRAME_RATE as f64).abs() < 1e-6,
+                "seeking to {secs} decoded the frame at {time}"
+            );
+            assert!(
+                frame_luma(&frame).abs_diff(luma(expected)) <= 2,
+                "seeking to {secs} decoded luma {} instead of {}",
+                frame_luma(&frame),
+                luma(expected)
+            );
+        }
+
+        std::fs::remove_file(input).ok();
+    }
+}
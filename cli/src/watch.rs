@@ -0,0 +1,190 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::batch::{is_video, run_batch, BatchEvent, BatchOptions, Montage};
+use crate::channel::unbounded;
+
+/// json lines log in the output directory, one [`WatchLogEntry`] per processed recording
+pub const WATCH_LOG: &str = "eclipper_watch.log.jsonl";
+
+/// Settings of [`watch`]
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// directory recordings are saved to, e.g. the OBS output folder
+    pub dir: PathBuf,
+    /// montages, caches and the log are written here
+    pub output_dir: PathBuf,
+    pub poll_interval: Duration,
+    /// how long the size of a recording has to stay the same before it is processed
+    pub settle_time: Duration,
+    /// also process recordings that were already in `dir` when watching started
+    pub include_existing: bool,
+    /// detection settings, the montage and cache paths are replaced by `output_dir`
+    pub batch: BatchOptions,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchLogEntry {
+    pub input: PathBuf,
+    /// a recording is processed again if it changes
+    pub file_size: u64,
+    /// unix timestamp in seconds
+    pub processed_at: u64,
+    pub clips: usize,
+    pub output: Option<PathBuf>,
+    pub error: Option<String>,
+}
+
+/// size and modification time of a recording that is possibly still being written
+struct Pending {
+    size: u64,
+    modified: Option<SystemTime>,
+    since: Instant,
+}
+
+/// Polls `options.dir` for new recordings and processes each once it is finished writing.
+/// Runs until the directory can't be read anymore.
+/// Recordings in the log are skipped, so watching can be stopped and resumed
+pub fn watch(options: &WatchOptions) -> Result<(), String> {
+    let dir = std::fs::canonicalize(&options.dir)
+        .map_err(|err| format!("failed to open {}: {err}", options.dir.display()))?;
+    std::fs::create_dir_all(&options.output_dir)
+        .map_err(|err| format!("failed to create {}: {err}", options.output_dir.display()))?;
+    let log_path = options.output_dir.join(WATCH_LOG);
+
+    let mut processed: HashSet<(PathBuf, u64)> = read_log(&log_path)
+        .into_iter()
+        .map(|entry| (entry.input, entry.file_size))
+        .collect();
+    if !options.include_existing {
+        for path in recordings(&dir)? {
+            if let Ok(metadata) = std::fs::metadata(&path) {
+                processed.insert((path, metadata.len()));
+            }
+        }
+    }
+
+    let batch = BatchOptions {
+        montage: Montage::PerFile {
+            output_dir: Some(options.output_dir.clone()),
+        },
+        cache_dir: Some(options.output_dir.clone()),
+        ..options.batch.clone()
+    };
+    let mut pending: HashMap<PathBuf, Pending> = HashMap::new();
+    info!("watching {} for new recordings", dir.display());
+
+    loop {
+        let found = recordings(&dir)?;
+        pending.retain(|path, _| found.contains(path));
+
+        for path in found {
+            let Ok(metadata) = std::fs::metadata(&path) else {
+                continue;
+            };
+            let size = metadata.len();
+            if processed.contains(&(path.clone(), size)) {
+                continue;
+            }
+
+            let modified = metadata.modified().ok();
+            let state = pending.entry(path.clone()).or_insert(Pending {
+                size,
+                modified,
+                since: Instant::now(),
+            });
+            if state.size != size || state.modified != modified {
+                *state = Pending {
+                    size,
+                    modified,
+                    since: Instant::now(),
+                };
+                continue;
+            }
+            if state.since.elapsed() < options.settle_time || !is_closed(&path) {
+                continue;
+            }
+            pending.remove(&path);
+
+            let entry = process(&path, size, &batch);
+            if let Err(err) = append_log(&log_path, &entry) {
+                error!("failed to write {}: {err}", log_path.display());
+            }
+            processed.insert((path, size));
+        }
+
+        std::thread::sleep(options.poll_interval);
+    }
+}
+
+fn process(input: &Path, file_size: u64, options: &BatchOptions) -> WatchLogEntry {
+    let (events_tx, events_rx) = unbounded();
+    let item = run_batch(vec![input.to_path_buf()], options, events_tx)
+        .pop()
+        .unwrap();
+    let output = events_rx.try_iter().find_map(|event| match event {
+        BatchEvent::Finished { output, .. } => output,
+        _ => None,
+    });
+    if let Some(output) = &output {
+        info!("montage written to {}", output.display());
+    }
+
+    let processed_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    WatchLogEntry {
+        input: input.to_path_buf(),
+        file_size,
+        processed_at,
+        clips: item.result.as_ref().map(|r| r.clips.len()).unwrap_or(0),
+        output,
+        error: item.result.err(),
+    }
+}
+
+/// videos in `dir`, without the montages written by the watcher itself
+fn recordings(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|err| format!("failed to read {}: {err}", dir.display()))?;
+    Ok(entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && is_video(path))
+        .filter(|path| {
+            !path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().contains(".eclipper_trimmed."))
+        })
+        .collect())
+}
+
+/// Recorders keep the file open for writing until the recording stops,
+/// on windows it can't be opened for writing by anyone else until then
+fn is_closed(path: &Path) -> bool {
+    OpenOptions::new().append(true).open(path).is_ok()
+}
+
+pub fn read_log(path: &Path) -> Vec<WatchLogEntry> {
+    let Ok(file) = std::fs::File::open(path) else {
+        return Vec::new();
+    };
+    BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect()
+}
+
+fn append_log(path: &Path, entry: &WatchLogEntry) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let line = serde_json::to_string(entry)?;
+    writeln!(file, "{line}")
+}
@@ -0,0 +1,267 @@
+use std::path::Path;
+
+use bincode::{Decode, Encode};
+use ffmpeg::format::sample::Type;
+use ffmpeg::format::Sample;
+use ffmpeg::{codec, format, frame, media, Error};
+use ffmpeg_next as ffmpeg;
+use serde::Serialize;
+use tracing::info;
+
+use crate::cache_clips::{cache_waveform, waveform_from_cache};
+use crate::video_decoder::t_to_secs;
+
+/// zoom levels stored for every audio stream, finest first.
+/// each coarser level must divide the finest one
+pub const PEAKS_PER_SECOND: &[u32] = &[50, 10, 2];
+
+#[derive(Encode, Decode, Serialize, Clone, Debug)]
+pub struct Waveform {
+    pub streams: Box<[StreamPeaks]>,
+}
+
+#[derive(Encode, Decode, Serialize, Clone, Debug)]
+pub struct StreamPeaks {
+    /// index of the audio stream in the input file
+    pub stream_index: u32,
+    /// one entry per value in [`PEAKS_PER_SECOND`]
+    pub levels: Box<[PeakLevel]>,
+}
+
+#[derive(Encode, Decode, Serialize, Clone, Debug)]
+pub struct PeakLevel {
+    pub peaks_per_second: u32,
+    /// [min, max] sample value in -1.0..=1.0 of every channel, peak `i` starts at `i / peaks_per_second` seconds
+    pub peaks: Box<[[f32; 2]]>,
+}
+
+/// the coarsest stored level with at least `peaks_per_second`, or the finest level
+pub fn level_for(peaks_per_second: u32) -> u32 {
+    PEAKS_PER_SECOND
+        .iter()
+        .rev()
+        .copied()
+        .find(|pps| *pps >= peaks_per_second)
+        .unwrap_or(PEAKS_PER_SECOND[0])
+}
+
+impl StreamPeaks {
+    pub fn level(&self, peaks_per_second: u32) -> Option<&PeakLevel> {
+        self.levels
+            .iter()
+            .find(|level| level.peaks_per_second == peaks_per_second)
+    }
+}
+
+impl PeakLevel {
+    /// peaks between `start` and `end` seconds
+    pub fn range(&self, start: f64, end: f64) -> &[[f32; 2]] {
+        let pps = self.peaks_per_second as f64;
+        let start = ((start.max(0.) * pps) as usize).min(self.peaks.len());
+        let end = ((end.max(0.) * pps).ceil() as usize).clamp(start, self.peaks.len());
+        &self.peaks[start..end]
+    }
+}
+
+/// Returns the cached waveform of `input` or decodes every audio stream and caches the result.
+/// The cache is next to the input unless `cache_dir` is given
+pub fn load_or_extract_waveform(input: &Path, cache_dir: Option<&Path>) -> Result<Waveform, Error> {
+    if let Some(waveform) = waveform_from_cache(input, cache_dir) {
+        return Ok(waveform);
+    }
+    let waveform = extract_waveform(input)?;
+    cache_waveform(input, cache_dir, waveform.clone());
+    Ok(waveform)
+}
+
+/// Decodes all audio streams once and computes min/max peaks at every zoom level in [`PEAKS_PER_SECOND`]
+pub fn extract_waveform(input: &Path) -> Result<Waveform, Error> {
+    let mut ictx = format::input(&input)?;
+
+    struct AudioStream {
+        stream_index: usize,
+        time_base: ffmpeg::Rational,
+        decoder: codec::decoder::Audio,
+        peaks: Vec<[f32; 2]>,
+    }
+
+    let mut audio_streams = Vec::new();
+    for stream in ictx.streams() {
+        if stream.parameters().medium() != media::Type::Audio {
+            continue;
+        }
+        let decoder = codec::context::Context::from_parameters(stream.parameters())?
+            .decoder()
+            .audio()?;
+        audio_streams.push(AudioStream {
+            stream_index: stream.index(),
+            time_base: stream.time_base(),
+            decoder,
+            peaks: Vec::new(),
+        });
+    }
+    info!(
+        "extracting waveform of {} audio stream(s)",
+        audio_streams.len()
+    );
+
+    let mut decoded = frame::Audio::empty();
+    for (stream, packet) in ictx.packets() {
+        let Some(audio) = audio_streams
+            .iter_mut()
+            .find(|a| a.stream_index == stream.index())
+        else {
+            continue;
+        };
+        if audio.decoder.send_packet(&packet).is_err() {
+            continue;
+        }
+        while audio.decoder.receive_frame(&mut decoded).is_ok() {
+            let start = decoded
+                .pts()
+                .map_or(0., |pts| t_to_secs(pts, audio.time_base));
+            add_frame_peaks(&decoded, start, &mut audio.peaks);
+        }
+    }
+    for audio in audio_streams.iter_mut() {
+        audio.decoder.send_eof()?;
+        while audio.decoder.receive_frame(&mut decoded).is_ok() {
+            let start = decoded
+                .pts()
+                .map_or(0., |pts| t_to_secs(pts, audio.time_base));
+            add_frame_peaks(&decoded, start, &mut audio.peaks);
+        }
+    }
+
+    let streams = audio_streams
+        .into_iter()
+        .map(|audio| StreamPeaks {
+            stream_index: audio.stream_index as u32,
+            levels: PEAKS_PER_SECOND
+                .iter()
+                .map(|pps| PeakLevel {
+                    peaks_per_second: *pps,
+                    peaks: downsample(&audio.peaks, (PEAKS_PER_SECOND[0] / pps) as usize),
+                })
+                .collect(),
+        })
+        .collect();
+
+    Ok(Waveform { streams })
+}
+
+/// merge every `factor` peaks into one
+fn downsample(peaks: &[[f32; 2]], factor: usize) -> Box<[[f32; 2]]> {
+    peaks
+        .chunks(factor.max(1))
+        .map(|chunk| {
+            chunk.iter().fold([0_f32, 0_f32], |acc, peak| {
+                [acc[0].min(peak[0]), acc[1].max(peak[1])]
+            })
+        })
+        .collect()
+}
+
+/// update the finest level peaks with the samples of `frame`, which starts at `start` seconds
+fn add_frame_peaks(frame: &frame::Audio, start: f64, peaks: &mut Vec<[f32; 2]>) {
+    let rate = frame.rate() as u64;
+    if rate == 0 {
+        return;
+    }
+    let pps = PEAKS_PER_SECOND[0] as u64;
+    let first_sample = (start.max(0.) * rate as f64) as u64;
+
+    for_each_sample(frame, |sample, value| {
+        let index = ((first_sample + sample as u64) * pps / rate) as usize;
+        if peaks.len() <= index {
+            peaks.resize(index + 1, [0., 0.]);
+        }
+        let peak = &mut peaks[index];
+        peak[0] = peak[0].min(value);
+        peak[1] = peak[1].max(value);
+    });
+}
+
+/// calls `f` with the sample index and the value normalised to -1.0..=1.0 for every channel of every sample
+fn for_each_sample(frame: &frame::Audio, mut f: impl FnMut(usize, f32)) {
+    let (bytes, planar) = match frame.format() {
+        Sample::U8(t) => (1, t == Type::Planar),
+        Sample::I16(t) => (2, t == Type::Planar),
+        Sample::I32(t) | Sample::F32(t) => (4, t == Type::Planar),
+        Sample::I64(t) | Sample::F64(t) => (8, t == Type::Planar),
+        Sample::None => return,
+    };
+    let format = frame.format();
+    let channels = frame.channels().max(1) as usize;
+    let samples = frame.samples();
+    let (planes, values_per_plane) = if planar {
+        (channels, samples)
+    } else {
+        (1, samples * channels)
+    };
+    let values_per_sample = if planar { 1 } else { channels };
+
+    for plane in 0..planes {
+        // for audio only linesize[0] is set, so `frame.data(plane)` can't be used for planar formats
+        let data = unsafe {
+            let ptr = *(*frame.as_ptr()).extended_data.add(plane);
+            if ptr.is_null() {
+                return;
+            }
+            std::slice::from_raw_parts(ptr, values_per_plane * bytes)
+        };
+        for (i, value) in data.chunks_exact(bytes).enumerate() {
+            let value = match format {
+                Sample::U8(_) => (value[0] as f32 - 128.) / 128.,
+                Sample::I16(_) => i16::from_ne_bytes([value[0], value[1]]) as f32 / i16::MAX as f32,
+                Sample::I32(_) => {
+                    i32::from_ne_bytes(value.try_into().unwrap()) as f32 / i32::MAX as f32
+                }
+                Sample::I64(_) => {
+                    i64::from_ne_bytes(value.try_into().unwrap()) as f32 / i64::MAX as f32
+                }
+                Sample::F32(_) => f32::from_ne_bytes(value.try_into().unwrap()),
+                Sample::F64(_) => f64::from_ne_bytes(value.try_into().unwrap()) as f32,
+                Sample::None => 0.,
+            };
+            f(i / values_per_sample, value.clamp(-1., 1.));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downsample_keeps_the_extremes_of_every_chunk() {
+        let peaks = [[-0.5, 0.2], [-0.1, 0.9], [0., 0.], [-0.3, 0.4], [-0.2, 0.1]];
+        assert_eq!(
+            *downsample(&peaks, 2),
+            [[-0.5, 0.9], [-0.3, 0.4], [-0.2, 0.1]]
+        );
+        assert_eq!(*downsample(&peaks, 1), peaks);
+        assert_eq!(*downsample(&peaks, 0), peaks);
+        assert_eq!(*downsample(&peaks, 10), [[-0.5, 0.9]]);
+        assert!(downsample(&[], 5).is_empty());
+    }
+
+    #[test]
+    fn range_covers_the_requested_seconds() {
+        let level = PeakLevel {
+            peaks_per_second: 10,
+            peaks: (0..50).map(|i| [-(i as f32), i as f32]).collect(),
+        };
+        let first = |peaks: &[[f32; 2]]| peaks.first().map(|peak| peak[1]);
+        assert_eq!(level.range(1., 2.).len(), 10);
+        assert_eq!(first(level.range(1., 2.)), Some(10.));
+        // partial peaks at both ends are included
+        assert_eq!(level.range(1.05, 1.21).len(), 3);
+        assert_eq!(first(level.range(1.05, 1.21)), Some(10.));
+        // clamped to the peaks there are
+        assert_eq!(level.range(-1., 0.5).len(), 5);
+        assert_eq!(level.range(4.5, 10.).len(), 5);
+        assert!(level.range(6., 7.).is_empty());
+        assert!(level.range(2., 1.).is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+
+use common::batch::{expand_inputs, run_batch, BatchOptions, Montage};
+use common::channel::unbounded;
+use common::{DetectionArgs, EventKind, DEFAULT_MULTI_KILL_WINDOW};
+
+use crate::project::Projects;
+use crate::{resource_dir, tesseract_options};
+
+/// only one batch runs at a time, it already uses all the threads it is given
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// resets [`RUNNING`] even if writing a montage panics
+struct RunningGuard;
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::Release);
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum MontageOption {
+    None,
+    #[serde(rename_all = "camelCase")]
+    PerFile {
+        output_dir: Option<PathBuf>,
+    },
+    Combined {
+        output: PathBuf,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchResult {
+    pub input: PathBuf,
+    /// id of the project the recording was opened as, `None` if it failed
+    pub project: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Processes all recordings, `parallel` at a time, emitting `batch` events with the progress of each.
+/// Processed recordings are opened as projects so they can be edited without reprocessing
+#[tauri::command(async)]
+#[allow(clippy::too_many_arguments)]
+pub fn process_batch(
+    inputs: Vec<PathBuf>,
+    threads: usize,
+    parallel: usize,
+    include_assists: bool,
+    include_spectating: bool,
+    include_deaths: bool,
+    elim_clip_duration: f64,
+    pre_roll: Vec<(EventKind, f64)>,
+    post_roll: Vec<(EventKind, f64)>,
+    multi_kills_only: bool,
+    hw_accel: bool,
+    psm: Option<u8>,
+    oem: Option<u8>,
+    montage: MontageOption,
+    app_handle: AppHandle,
+    projects: State<Arc<Projects>>,
+) -> Result<Vec<BatchResult>, String> {
+    if RUNNING.swap(true, Ordering::AcqRel) {
+        return Err("a batch is already running".to_string());
+    }
+    let _guard = RunningGuard;
+
+    let inputs = expand_inputs(&inputs);
+    let options = BatchOptions {
+        detection: DetectionArgs {
+            include_assists,
+            include_spectating,
+            include_deaths,
+            elim_clip_duration,
+            pre_roll,
+            post_roll,
+            multi_kill_window: DEFAULT_MULTI_KILL_WINDOW,
+            multi_kills_only,
+            tesseract: tesseract_options(&app_handle, psm, oem)?,
+            templates: resource_dir(&app_handle, "templates"),
+        },
+        threads,
+        parallel,
+        hw_accel,
+        montage: match montage {
+            MontageOption::None => Montage::None,
+            MontageOption::PerFile { output_dir } => Montage::PerFile { output_dir },
+            MontageOption::Combined { output } => Montage::Combined(output),
+        },
+        cache_dir: None,
+    };
+
+    let (events_tx, events_rx) = unbounded();
+    let forwarder = std::thread::spawn(move || {
+        while let Ok(event) = events_rx.recv() {
+            app_handle.emit("batch", event).ok();
+        }
+    });
+    let items = run_batch(inputs, &options, events_tx);
+    forwarder.join().ok();
+
+    let results = items
+        .into_iter()
+        .map(|item| match item.result {
+            Ok(result) => {
+                let id = projects.open(&item.input);
+                projects.get(&id).unwrap().set_result(result);
+                BatchResult {
+                    input: item.input,
+                    project: Some(id),
+                    error: None,
+                }
+            }
+            Err(error) => BatchResult {
+                input: item.input,
+                project: None,
+                error: Some(error),
+            },
+        })
+        .collect();
+    Ok(results)
+}
@@ -1,25 +1,55 @@
 use std::collections::Bound;
+use std::hash::BuildHasher;
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::num::NonZeroUsize;
-use std::sync::{Mutex, OnceLock};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
 
-use axum::extract::Query;
-use axum::http::{header, Response};
+use axum::extract::rejection::{PathRejection, QueryRejection};
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, Response, StatusCode};
 use axum::response::IntoResponse;
 use axum_extra::TypedHeader;
 use bytes::Bytes;
 use headers::Header;
 use lru::LruCache;
-use serde::Deserialize;
-
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{error, info};
+
+use common::process_frame::{scale_frame, FilterType, ResizeAlg};
+use common::waveform::{self, Waveform};
 use common::{PreviewFrame, VideoDecoder};
 
-use crate::{KEYFRAMES, VIDEO_FILE};
-pub static CLIP_CACHE: Cache = Cache::new();
+use crate::project::{Project, Projects};
 
 // use actix_files as fs;
 // use actix_web::{get, App, Error, HttpRequest, HttpServer};
 
 pub static PORT: OnceLock<u16> = OnceLock::new();
+/// remuxed clips of all projects, bounded as a whole so opening more projects doesn't grow it
+#[allow(clippy::type_complexity)]
+static CLIP_CACHE: Mutex<Option<LruCache<(String, Clip), Arc<ClipFile>>>> = Mutex::new(None);
+/// total size of the files in [`CLIP_CACHE`] in bytes, the most recently used clip is always kept
+const CLIP_CACHE_SIZE: u64 = 1_000_000 * 500;
+/// clips are always remuxed to fragmented mp4
+const CLIP_CONTENT_TYPE: &str = "video/mp4";
+/// random per launch, every route is nested under it so other local processes can't read the footage
+static TOKEN: OnceLock<String> = OnceLock::new();
+
+fn token() -> &'static str {
+    TOKEN.get_or_init(|| {
+        rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect()
+    })
+}
 
 // #[get("/{filename:.*}")]
 // async fn index(req: HttpRequest) -> Result<fs::NamedFile, Error> {
@@ -28,11 +58,13 @@ pub static PORT: OnceLock<u16> = OnceLock::new();
 //     Ok(file)
 // }
 //
+/// base url of the file server including the access token, routes are relative to `<address>/<project>/`
 #[tauri::command]
 pub fn get_file_server_address() -> String {
     format!(
-        "http://127.0.0.1:{}",
-        PORT.get().copied().unwrap_or_default()
+        "http://127.0.0.1:{}/{}",
+        PORT.get().copied().unwrap_or_default(),
+        token()
     )
 }
 
@@ -47,23 +79,182 @@ pub fn get_file_server_address() -> String {
 // }
 
 #[derive(Debug, Deserialize)]
-#[allow(dead_code)]
+struct ProjectPath {
+    project: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FramePath {
+    project: String,
+    pts: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct SpritePath {
+    project: String,
+    name: String,
+}
+
+fn get_project(projects: &Projects, id: &str) -> Result<Arc<Project>, ServerError> {
+    projects
+        .get(id)
+        .ok_or_else(|| ServerError::not_found(format!("unknown project {id}")))
+}
+
+fn path_params<T>(path: Result<axum::extract::Path<T>, PathRejection>) -> Result<T, ServerError> {
+    path.map(|axum::extract::Path(path)| path)
+        .map_err(|err| ServerError::bad_request(err.body_text()))
+}
+
+#[derive(Debug, Deserialize)]
 struct Params {
     start: f64,
     end: f64,
 }
 
-async fn index(
-    Query(params): Query<Params>,
+/// Serves `start..end` remuxed to fragmented mp4. While the clip is remuxed its fragments are
+/// sent as they are written, range requests are answered with the whole clip until then.
+/// Once the remux finished the clip is served from its file with support for conditional and
+/// (multi) range requests
+async fn stream(
+    State(projects): State<Arc<Projects>>,
+    path: Result<axum::extract::Path<ProjectPath>, PathRejection>,
+    params: Result<Query<Params>, QueryRejection>,
     range: Option<TypedHeader<headers::Range>>,
-) -> impl IntoResponse {
-    let result = tokio::task::block_in_place(|| CLIP_CACHE.get_clip((params.start, params.end)));
+    if_range: Option<TypedHeader<headers::IfRange>>,
+    if_none_match: Option<TypedHeader<headers::IfNoneMatch>>,
+) -> Result<Response<Body>, ServerError> {
+    let id = path_params(path)?.project;
+    let project = get_project(&projects, &id)?;
+    let Query(params) = params.map_err(|err| ServerError::bad_request(err.body_text()))?;
+    let duration = tokio::task::block_in_place(|| project.duration())
+        .map_err(|err| ServerError::internal(format!("failed to read duration: {err}")))?;
+    // checked before remuxing, the response can't turn into an error once it has started.
+    // the end may be past the end of the video, the remux stops at the end of the file
+    if !(0. ..duration).contains(&params.start) || params.end.is_nan() || params.end <= params.start
+    {
+        return Err(ServerError::bad_request(format!(
+            "invalid clip {}..{}, the video is {duration:.3}s long",
+            params.start, params.end
+        )));
+    }
+    let clip = Clip::from((params.start, params.end));
+    let etag = clip.etag(&project.input);
+
+    if let Some(TypedHeader(if_none_match)) = if_none_match {
+        if !if_none_match.precondition_passes(&etag) {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::NOT_MODIFIED;
+            insert_header(response.headers_mut(), etag);
+            return Ok(response);
+        }
+    }
 
-    let data_len = result.as_ref().map(|data| data.len()).unwrap_or_default();
+    let file = clip_file(&id, &project, params.start, params.end)
+        .await
+        .map_err(|err| ServerError::internal(format!("failed to remux clip: {err}")))?;
+    let Some(file_len) = file.len() else {
+        // the length isn't known yet, ranges can't be resolved against it
+        return Ok(with_clip_headers(
+            Response::new(tail_body(file)),
+            etag,
+            Some(CLIP_CONTENT_TYPE),
+        ));
+    };
 
-    let mut ranges = Vec::new();
-    if let Some(TypedHeader(range)) = range {
-        for (start, end) in range.satisfiable_ranges(data_len as u64) {
+    let range = requested_ranges(
+        range.as_ref().map(|TypedHeader(range)| range),
+        if_range.as_ref().map(|TypedHeader(if_range)| if_range),
+        &etag,
+        file_len,
+    );
+
+    let response = match range {
+        None => {
+            let mut response = Response::new(file_body(file, vec![BodyPart::File(0..file_len)]));
+            insert_header(response.headers_mut(), headers::ContentLength(file_len));
+            response
+        }
+        Some(ranges) if ranges.is_empty() => {
+            let mut response = ServerError {
+                status: StatusCode::RANGE_NOT_SATISFIABLE,
+                message: "range not satisfiable".to_string(),
+            }
+            .into_response();
+            insert_header(
+                response.headers_mut(),
+                headers::ContentRange::unsatisfied_bytes(file_len),
+            );
+            return Ok(response);
+        }
+        Some(ranges) if ranges.len() == 1 => {
+            let range = ranges[0].clone();
+            let content_range = headers::ContentRange::bytes(range.clone(), file_len)
+                .map_err(|_| ServerError::internal("invalid content range"))?;
+            let len = range.end - range.start;
+            let mut response = Response::new(file_body(file, vec![BodyPart::File(range)]));
+            *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+            let headers = response.headers_mut();
+            insert_header(headers, content_range);
+            insert_header(headers, headers::ContentLength(len));
+            response
+        }
+        Some(ranges) => {
+            let boundary = format!(
+                "{:016x}",
+                std::collections::hash_map::RandomState::new()
+                    .build_hasher()
+                    .finish()
+            );
+            let mut parts = Vec::new();
+            for range in ranges {
+                parts.push(BodyPart::Bytes(Bytes::from(format!(
+                    "\r\n--{boundary}\r\nContent-Type: {CLIP_CONTENT_TYPE}\r\nContent-Range: bytes {}-{}/{file_len}\r\n\r\n",
+                    range.start,
+                    range.end - 1
+                ))));
+                parts.push(BodyPart::File(range));
+            }
+            parts.push(BodyPart::Bytes(Bytes::from(format!(
+                "\r\n--{boundary}--\r\n"
+            ))));
+
+            let body_len = parts.iter().map(BodyPart::len).sum();
+            let mut response = Response::new(file_body(file, parts));
+            *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+            let headers = response.headers_mut();
+            headers.insert(
+                header::CONTENT_TYPE,
+                format!("multipart/byteranges; boundary={boundary}")
+                    .parse()
+                    .unwrap(),
+            );
+            insert_header(headers, headers::ContentLength(body_len));
+            return Ok(with_clip_headers(response, etag, None));
+        }
+    };
+
+    Ok(with_clip_headers(response, etag, Some(CLIP_CONTENT_TYPE)))
+}
+
+/// The ranges to serve of a clip that's `len` bytes long, `None` if it's served in full because
+/// there's no `Range` header or the `If-Range` header shows the client's copy is outdated
+fn requested_ranges(
+    range: Option<&headers::Range>,
+    if_range: Option<&headers::IfRange>,
+    etag: &headers::ETag,
+    len: u64,
+) -> Option<Vec<std::ops::Range<u64>>> {
+    range
+        .filter(|_| if_range.map_or(true, |if_range| !if_range.is_modified(Some(etag), None)))
+        .map(|range| satisfiable_ranges(range, len))
+}
+
+/// ranges of a `Range` header as `start..end` byte offsets, clamped to `len`
+fn satisfiable_ranges(range: &headers::Range, len: u64) -> Vec<std::ops::Range<u64>> {
+    range
+        .satisfiable_ranges(len)
+        .filter_map(|(start, end)| {
             let start = match start {
                 Bound::Included(start) => start,
                 Bound::Excluded(start) => start + 1,
@@ -72,130 +263,541 @@ async fn index(
             let end = match end {
                 Bound::Included(end) => end + 1,
                 Bound::Excluded(end) => end,
-                Bound::Unbounded => data_len as u64,
-            };
-            ranges.push(start as usize..end as usize);
+                Bound::Unbounded => len,
+            }
+            .min(len);
+            (start < end).then_some(start..end)
+        })
+        .collect()
+}
+
+fn with_clip_headers(
+    mut response: Response<Body>,
+    etag: headers::ETag,
+    content_type: Option<&str>,
+) -> Response<Body> {
+    let headers = response.headers_mut();
+    insert_header(headers, headers::AcceptRanges::bytes());
+    insert_header(headers, etag);
+    if let Some(content_type) = content_type {
+        headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+    }
+    response
+}
+
+fn insert_header<H: Header>(headers: &mut HeaderMap, header: H) {
+    let mut values = Vec::new();
+    header.encode(&mut values);
+    assert_eq!(values.len(), 1);
+    headers.insert(H::name(), values.remove(0));
+}
+
+/// Error response with a json body: `{"error": "..."}`
+#[derive(Debug)]
+struct ServerError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ServerError {
+    fn bad_request(message: impl Into<String>) -> ServerError {
+        ServerError {
+            status: StatusCode::BAD_REQUEST,
+            message: message.into(),
         }
     }
 
-    let data = if !ranges.is_empty() {
-        let range = ranges[0].clone();
-        result.map(|data| {
-            (
-                headers::ContentRange::bytes(range.start as u64..range.end as u64, data_len as u64),
-                data.slice(range),
-            )
-        })
+    fn unauthorized() -> ServerError {
+        ServerError {
+            status: StatusCode::UNAUTHORIZED,
+            message: "missing or invalid token".to_string(),
+        }
+    }
+
+    fn not_found(message: impl Into<String>) -> ServerError {
+        ServerError {
+            status: StatusCode::NOT_FOUND,
+            message: message.into(),
+        }
+    }
+
+    fn internal(message: impl Into<String>) -> ServerError {
+        let message = message.into();
+        error!("{message}");
+        ServerError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message,
+        }
+    }
+}
+
+impl IntoResponse for ServerError {
+    fn into_response(self) -> axum::response::Response {
+        (
+            self.status,
+            axum::Json(serde_json::json!({ "error": self.message })),
+        )
+            .into_response()
+    }
+}
+
+async fn get_frame(
+    State(projects): State<Arc<Projects>>,
+    path: Result<axum::extract::Path<FramePath>, PathRejection>,
+) -> Result<Response<Body>, ServerError> {
+    let FramePath { project, pts } = path_params(path)?;
+    let frame = get_project(&projects, &project)?
+        .cache
+        .get_frame(pts)
+        .ok_or_else(|| ServerError::not_found(format!("no preview frame with pts {pts}")))?;
+    let mut response = Response::new(Body::from(frame));
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, "image/bmp".parse().unwrap());
+    Ok(response)
+}
+
+#[derive(Debug, Deserialize)]
+struct ThumbnailParams {
+    /// time in seconds
+    t: f64,
+    /// max height, the full video height is used if not set
+    h: Option<u32>,
+    #[serde(default)]
+    format: ImageFormat,
+}
+
+async fn get_thumbnail(
+    State(projects): State<Arc<Projects>>,
+    path: Result<axum::extract::Path<ProjectPath>, PathRejection>,
+    params: Result<Query<ThumbnailParams>, QueryRejection>,
+) -> Result<Response<Body>, ServerError> {
+    let project = get_project(&projects, &path_params(path)?.project)?;
+    let Query(params) = params.map_err(|err| ServerError::bad_request(err.body_text()))?;
+    let duration = tokio::task::block_in_place(|| project.duration())
+        .map_err(|err| ServerError::internal(format!("failed to read duration: {err}")))?;
+    // seeking outside of the video only finds the end of the file
+    if !(0. ..duration).contains(&params.t) {
+        return Err(ServerError::bad_request(format!(
+            "t has to be between 0 and {duration:.3}, got {}",
+            params.t
+        )));
+    }
+    let image = tokio::task::block_in_place(|| {
+        project
+            .cache
+            .get_thumbnail(&project.input, params.t, params.h, params.format)
+    })
+    .map_err(|err| ServerError::internal(format!("thumbnail: {err}")))?;
+
+    let mut response = Response::new(Body::from(image));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        params.format.content_type().parse().unwrap(),
+    );
+    Ok(response)
+}
+
+async fn get_sprite(
+    State(projects): State<Arc<Projects>>,
+    path: Result<axum::extract::Path<SpritePath>, PathRejection>,
+) -> Result<Response<Body>, ServerError> {
+    let SpritePath { project, name } = path_params(path)?;
+    let input = &get_project(&projects, &project)?.input;
+    let file = sprite_file(input, &name).filter(|_| sprites_exist(input));
+    let data = file
+        .and_then(|file| std::fs::read(file).ok())
+        .ok_or_else(|| ServerError::not_found(format!("no sprite file {name}")))?;
+
+    let content_type = if name == INDEX_FILE {
+        "text/vtt"
     } else {
-        result.map(|data| {
-            (
-                headers::ContentRange::bytes(0..data_len as u64, data_len as u64),
-                data,
-            )
-        })
+        "image/jpeg"
     };
+    let mut response = Response::new(Body::from(data));
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+    Ok(response)
+}
 
-    data.map_err(|err| format!("{err}"))
-        .map(|(content_range, data)| {
-            let data_len = data.len();
-            let body = axum::body::Body::from(data);
-            let mut response = Response::new(body);
-            let headers = response.headers_mut();
-            let mut values = Vec::new();
-            headers::AcceptRanges::bytes().encode(&mut values);
-            assert_eq!(values.len(), 1);
-            headers.insert(header::ACCEPT_RANGES, values.remove(0));
-            let mut status = axum::http::StatusCode::OK;
-
-            match content_range {
-                Ok(content_range) => {
-                    if !content_range
-                        .bytes_len()
-                        .is_some_and(|len| len == data_len as u64)
-                    {
-                        let mut values = Vec::new();
-                        content_range.encode(&mut values);
-                        assert_eq!(values.len(), 1);
-                        headers.insert(header::CONTENT_RANGE, values.remove(0));
-                        status = axum::http::StatusCode::PARTIAL_CONTENT;
-                    }
-                }
-                Err(err) => {
-                    eprintln!("content_range: {err}");
-                }
-            }
-            headers.insert(
-                header::CONTENT_LENGTH,
-                data_len.to_string().parse().unwrap(),
-            );
-            *response.status_mut() = status;
+#[derive(Debug, Deserialize)]
+struct WaveformParams {
+    start: f64,
+    end: f64,
+    /// desired resolution, the closest stored zoom level with at least this many peaks is used
+    #[serde(default = "default_peaks_per_second")]
+    pps: u32,
+}
 
-            response
-        })
+fn default_peaks_per_second() -> u32 {
+    waveform::PEAKS_PER_SECOND[0]
 }
 
-pub async fn get_frame(axum::extract::Path(pts): axum::extract::Path<i64>) -> Response<Body> {
-    if let Some(frame) = CLIP_CACHE.get_frame(pts) {
-        let body = axum::body::Body::from(frame);
-        let mut response = Response::new(body);
-        response
-            .headers_mut()
-            .insert(header::CONTENT_TYPE, "image/bmp".parse().unwrap());
-        return response;
-    }
-    let body = axum::body::Body::from(Bytes::new());
-    Response::new(body)
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WaveformResponse<'a> {
+    peaks_per_second: u32,
+    /// time of the first peak, `start` rounded down to a peak boundary
+    start: f64,
+    streams: Vec<WaveformStream<'a>>,
 }
 
-pub async fn serve() {
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WaveformStream<'a> {
+    stream_index: u32,
+    peaks: &'a [[f32; 2]],
+}
+
+async fn get_waveform(
+    State(projects): State<Arc<Projects>>,
+    path: Result<axum::extract::Path<ProjectPath>, PathRejection>,
+    params: Result<Query<WaveformParams>, QueryRejection>,
+) -> Result<Response<Body>, ServerError> {
+    let project = get_project(&projects, &path_params(path)?.project)?;
+    let Query(params) = params.map_err(|err| ServerError::bad_request(err.body_text()))?;
+    let waveform = project
+        .cache
+        .waveform()
+        .await
+        .map_err(|err| ServerError::internal(format!("waveform: {err}")))?;
+    let peaks_per_second = waveform::level_for(params.pps);
+    let start = (params.start.max(0.) * peaks_per_second as f64).floor() / peaks_per_second as f64;
+    let streams = waveform
+        .streams
+        .iter()
+        .filter_map(|stream| {
+            let level = stream.level(peaks_per_second)?;
+            Some(WaveformStream {
+                stream_index: stream.stream_index,
+                peaks: level.range(params.start, params.end),
+            })
+        })
+        .collect();
+    let json = serde_json::to_vec(&WaveformResponse {
+        peaks_per_second,
+        start,
+        streams,
+    })
+    .unwrap();
+
+    let mut response = Response::new(Body::from(json));
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+    Ok(response)
+}
+
+pub async fn serve(projects: Arc<Projects>) {
+    let routes = axum::Router::new()
+        .route("/:project/frame/:pts", axum::routing::get(get_frame))
+        .route("/:project/thumbnail", axum::routing::get(get_thumbnail))
+        .route("/:project/sprites/:name", axum::routing::get(get_sprite))
+        .route("/:project/waveform", axum::routing::get(get_waveform))
+        .route("/:project/stream", axum::routing::get(stream))
+        .fallback(|| async { ServerError::not_found("not found") })
+        .with_state(projects);
     let app = axum::Router::new()
-        .route("/frame/:pts", axum::routing::get(get_frame))
-        .route("/*O", axum::routing::get(index));
+        .nest(&format!("/{}", token()), routes)
+        .fallback(|| async { ServerError::unauthorized() });
+
+    std::fs::remove_dir_all(clip_dir()).ok();
 
     // run our app with hyper, listening globally on port 3000
     let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
-    PORT.get_or_init(|| listener.local_addr().unwrap().port());
-    println!("listening on: {}", get_file_server_address());
+    let port = *PORT.get_or_init(|| listener.local_addr().unwrap().port());
+    // the address without the access token, logs can end up in bug reports
+    info!("listening on: http://127.0.0.1:{port}");
     axum::serve(listener, app).await.unwrap();
 }
 
+/// A clip remuxed to a file in [`clip_dir`], deleted once it's evicted and no longer served
+struct ClipFile {
+    path: PathBuf,
+    progress: watch::Receiver<RemuxProgress>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct RemuxProgress {
+    /// bytes of the file written so far
+    written: u64,
+    /// set once the remux ended, with the error if it failed
+    done: Option<Result<(), String>>,
+}
+
+impl ClipFile {
+    /// size in bytes, only known once the remux finished
+    fn len(&self) -> Option<u64> {
+        let progress = self.progress.borrow();
+        matches!(progress.done, Some(Ok(()))).then_some(progress.written)
+    }
+
+    fn failed(&self) -> bool {
+        matches!(self.progress.borrow().done, Some(Err(_)))
+    }
+}
+
+impl Drop for ClipFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+/// The clip file, telling readers about every write
+struct ProgressWriter {
+    file: std::fs::File,
+    progress: watch::Sender<RemuxProgress>,
+}
+
+impl Write for ProgressWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.progress
+            .send_modify(|progress| progress.written += written as u64);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// remuxed clips are written here, cleared on startup in case the app didn't exit cleanly
+fn clip_dir() -> PathBuf {
+    std::env::temp_dir().join("the-finals-eclipper-clips")
+}
+
+/// Returns the remuxed clip of project `id`, shared by all projects in [`CLIP_CACHE`]. A clip
+/// that isn't cached is remuxed in the background, this returns once its header is written.
+/// Concurrent requests for the same clip read the same file
+async fn clip_file(
+    id: &str,
+    project: &Project,
+    start: f64,
+    end: f64,
+) -> Result<Arc<ClipFile>, String> {
+    let key = (id.to_string(), Clip::from((start, end)));
+    let file = {
+        let mut lock = CLIP_CACHE.lock().unwrap();
+        let lru = lock.get_or_insert_with(LruCache::unbounded);
+        // a failed remux is tried again
+        match lru.get(&key).filter(|file| !file.failed()) {
+            Some(file) => file.clone(),
+            None => {
+                let file = start_remux(project, start, end)?;
+                lru.put(key, file.clone());
+                file
+            }
+        }
+    };
+
+    let mut progress = file.progress.clone();
+    let result = progress
+        .wait_for(|progress| progress.written > 0 || progress.done.is_some())
+        .await
+        .map_err(|_| "remux stopped".to_string())?
+        .done
+        .clone();
+    if let Some(Err(err)) = result {
+        return Err(err);
+    }
+
+    if let Some(lru) = CLIP_CACHE.lock().unwrap().as_mut() {
+        let written = |file: &ClipFile| file.progress.borrow().written;
+        let mut size = lru.iter().map(|(_, file)| written(file)).sum::<u64>();
+        while size > CLIP_CACHE_SIZE && lru.len() >= 2 {
+            let (_, removed) = lru.pop_lru().unwrap();
+            size -= written(&removed);
+        }
+    }
+    Ok(file)
+}
+
+/// Starts remuxing `start..end` of the input of `project` to a new file on a blocking thread
+fn start_remux(project: &Project, start: f64, end: f64) -> Result<Arc<ClipFile>, String> {
+    std::fs::create_dir_all(clip_dir()).map_err(|err| err.to_string())?;
+    let path = clip_dir().join(format!("{:016x}.mp4", rand::random::<u64>()));
+    let file = std::fs::File::create(&path).map_err(|err| err.to_string())?;
+    let (tx, rx) = watch::channel(RemuxProgress::default());
+
+    let input = project.input.clone();
+    let key_frames = project.keyframes();
+    tokio::task::spawn_blocking(move || {
+        let writer = ProgressWriter {
+            file,
+            progress: tx.clone(),
+        };
+        let result = VideoDecoder::open(&input, false)
+            .and_then(|mut decoder| decoder.stream_range(start, end, &key_frames, writer))
+            .map_err(|err| err.to_string());
+        if let Err(err) = &result {
+            error!(
+                "failed to remux {start}..{end} of {}: {err}",
+                input.display()
+            );
+        }
+        tx.send_modify(|progress| progress.done = Some(result));
+    });
+
+    Ok(Arc::new(ClipFile { path, progress: rx }))
+}
+
+/// drops the cached clips of a closed project
+pub fn evict_clips(id: &str) {
+    if let Some(lru) = CLIP_CACHE.lock().unwrap().as_mut() {
+        let keys: Vec<_> = lru
+            .iter()
+            .filter(|((project, _), _)| project == id)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in keys {
+            lru.pop(&key);
+        }
+    }
+}
+
+/// A piece of a response body, either literal bytes or a byte range of a clip file
+enum BodyPart {
+    Bytes(Bytes),
+    File(std::ops::Range<u64>),
+}
+
+impl BodyPart {
+    fn len(&self) -> u64 {
+        match self {
+            BodyPart::Bytes(bytes) => bytes.len() as u64,
+            BodyPart::File(range) => range.end - range.start,
+        }
+    }
+}
+
+/// Sends `file` while it's remuxed, each write is passed on as soon as it is in the file
+fn tail_body(file: Arc<ClipFile>) -> Body {
+    // a few chunks at most are buffered while the client catches up
+    let (tx, rx) = mpsc::channel(8);
+    tokio::spawn(async move {
+        if let Err(err) = send_tail(&file, &tx).await {
+            error!("failed to send clip: {err}");
+            tx.send(Err(err)).await.ok();
+        }
+    });
+    Body::from_stream(ReceiverStream::new(rx))
+}
+
+async fn send_tail(
+    file: &ClipFile,
+    tx: &mpsc::Sender<Result<Bytes, std::io::Error>>,
+) -> std::io::Result<()> {
+    let mut reader = tokio::fs::File::open(&file.path).await?;
+    let mut progress = file.progress.clone();
+    let mut sent = 0;
+    loop {
+        let RemuxProgress { written, done } = progress.borrow_and_update().clone();
+        while sent < written {
+            let mut chunk = vec![0; (written - sent).min(64 * 1024) as usize];
+            reader.read_exact(&mut chunk).await?;
+            sent += chunk.len() as u64;
+            if tx.send(Ok(Bytes::from(chunk))).await.is_err() {
+                // the client disconnected
+                return Ok(());
+            }
+        }
+        match done {
+            Some(Ok(())) => return Ok(()),
+            Some(Err(err)) => return Err(std::io::Error::other(err)),
+            None => {
+                if progress.changed().await.is_err() {
+                    return Err(std::io::Error::other("remux stopped"));
+                }
+            }
+        }
+    }
+}
+
+/// Streams `parts` of `file` from a blocking thread, the clip is never held in memory
+fn file_body(file: Arc<ClipFile>, parts: Vec<BodyPart>) -> Body {
+    // a few chunks at most are buffered while the client catches up
+    let (tx, rx) = mpsc::channel(8);
+    tokio::task::spawn_blocking(move || {
+        if let Err(err) = send_parts(&file.path, parts, &tx) {
+            error!("failed to read clip: {err}");
+            tx.blocking_send(Err(err)).ok();
+        }
+    });
+    Body::from_stream(ReceiverStream::new(rx))
+}
+
+fn send_parts(
+    path: &Path,
+    parts: Vec<BodyPart>,
+    tx: &mpsc::Sender<Result<Bytes, std::io::Error>>,
+) -> std::io::Result<()> {
+    let mut file = std::fs::File::open(path)?;
+    for part in parts {
+        match part {
+            BodyPart::Bytes(bytes) => {
+                if tx.blocking_send(Ok(bytes)).is_err() {
+                    // the client disconnected
+                    return Ok(());
+                }
+            }
+            BodyPart::File(range) => {
+                file.seek(SeekFrom::Start(range.start))?;
+                let mut remaining = range.end - range.start;
+                while remaining > 0 {
+                    let mut chunk = vec![0; remaining.min(64 * 1024) as usize];
+                    file.read_exact(&mut chunk)?;
+                    remaining -= chunk.len() as u64;
+                    if tx.blocking_send(Ok(Bytes::from(chunk))).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 pub struct Cache {
-    #[allow(clippy::type_complexity)]
-    clip_cache: Mutex<Option<LruCache<Clip, Bytes>>>,
     frame_cache: Mutex<Option<LruCache<i64, Bytes>>>,
+    thumbnail_cache: Mutex<Option<LruCache<Thumbnail, Bytes>>>,
+    /// `None` until [`Cache::extract_waveform`] is done
+    waveform: watch::Sender<Option<Result<Arc<Waveform>, String>>>,
 }
 
 impl Cache {
-    const fn new() -> Cache {
+    pub fn new() -> Cache {
         Cache {
-            clip_cache: Mutex::new(None),
             frame_cache: Mutex::new(None),
+            thumbnail_cache: Mutex::new(None),
+            waveform: watch::Sender::new(None),
         }
     }
 
-    fn get_clip(&self, clip: (f64, f64)) -> Result<Bytes, common::ffmpeg::Error> {
-        let mut lock = self.clip_cache.lock().unwrap();
-        let lru = lock.get_or_insert_with(|| LruCache::new(NonZeroUsize::new(20).unwrap()));
-        if let Some(data) = lru.get(&clip.into()).map(Bytes::clone) {
-            Self::limit_size(lru);
-            Ok(data)
-        } else {
-            drop(lock);
-            let path: std::path::PathBuf = {
-                let lock = VIDEO_FILE.lock().unwrap();
-                Option::clone(&lock).unwrap_or_default()
-            };
-            let mut decoder = VideoDecoder::open(&path, false)?;
-
-            let key_frames = { KEYFRAMES.read().unwrap().clone().unwrap_or_default() };
-
-            let result = decoder.transcode_range(clip.0, clip.1, &key_frames)?;
-            let mut lock = self.clip_cache.lock().unwrap();
-            let lru = lock.as_mut().unwrap();
-            lru.push(clip.into(), Bytes::from(result));
-            Self::limit_size(lru);
-            Ok(Bytes::clone(lru.get(&clip.into()).unwrap()))
+    /// Loads the waveform of `input` from its cache or extracts it. Blocks, it's called on its
+    /// own thread when the project is opened so the editor doesn't wait for the whole decode
+    pub fn extract_waveform(&self, input: &Path) {
+        let waveform = waveform::load_or_extract_waveform(input, None)
+            .map(Arc::new)
+            .map_err(|err| err.to_string());
+        if let Err(err) = &waveform {
+            error!(
+                "failed to extract the waveform of {}: {err}",
+                input.display()
+            );
         }
+        self.waveform.send_replace(Some(waveform));
+    }
+
+    /// the waveform of the input, waits until it has been extracted
+    async fn waveform(&self) -> Result<Arc<Waveform>, String> {
+        let mut waveform = self.waveform.subscribe();
+        let waveform = waveform
+            .wait_for(Option::is_some)
+            .await
+            .map_err(|err| err.to_string())?;
+        waveform.clone().unwrap()
     }
 
     fn get_frame(&self, pts: i64) -> Option<Bytes> {
@@ -206,6 +808,47 @@ impl Cache {
         lru.get(&pts).cloned()
     }
 
+    fn get_thumbnail(
+        &self,
+        input: &Path,
+        t: f64,
+        max_height: Option<u32>,
+        format: ImageFormat,
+    ) -> Result<Bytes, ThumbnailError> {
+        let key = Thumbnail(Timestamp(t), max_height, format);
+        {
+            let mut lock = self.thumbnail_cache.lock().unwrap();
+            if let Some(image) = lock.as_mut().and_then(|lru| lru.get(&key).cloned()) {
+                return Ok(image);
+            }
+        }
+
+        let mut decoder = VideoDecoder::open(input, false)?;
+        let frame = decoder.extract_frame(t)?;
+
+        let (pixels, width, height) = match max_height {
+            Some(max_height) if max_height > 0 && max_height < frame.height => {
+                let scale_factor = frame.height as f64 / max_height as f64;
+                let dst_width = (frame.width as f64 / scale_factor) as u32;
+                let pixels = scale_frame(
+                    &frame.pixels,
+                    frame.width as i32,
+                    frame.height as i32,
+                    max_height,
+                    ResizeAlg::Convolution(FilterType::Bilinear),
+                );
+                (pixels, dst_width, max_height)
+            }
+            _ => (frame.pixels.into_vec(), frame.width, frame.height),
+        };
+        let image = pixels_to_image(&pixels, width, height, format)?;
+
+        let mut lock = self.thumbnail_cache.lock().unwrap();
+        let lru = lock.get_or_insert_with(|| LruCache::new(NonZeroUsize::new(200).unwrap()));
+        lru.push(key, image.clone());
+        Ok(image)
+    }
+
     pub fn insert_frame(&self, mut preview_frame: PreviewFrame) -> Option<(i64, Bytes)> {
         let image = pixels_to_bmp(
             &mut preview_frame.pixels,
@@ -218,24 +861,16 @@ impl Cache {
         drop(lock);
         old
     }
-
-    fn limit_size(lru: &mut LruCache<Clip, Bytes>) {
-        // compute size
-        const HALF_GB: usize = 1_000_000 * 500;
-        // keep lru less than half gb
-        while lru.iter().map(|(_, d)| d.len()).sum::<usize>() > HALF_GB && lru.len() >= 2 {
-            println!("remove clip from cache due to total size of cache");
-            lru.pop_lru();
-        }
-    }
 }
 
-use crate::pixels_to_base64_image::pixels_to_bmp;
+use crate::pixels_to_base64_image::{pixels_to_bmp, pixels_to_image, ImageFormat};
+use crate::sprite_sheet::{sprite_file, sprites_exist, INDEX_FILE};
 use axum::body::Body;
 use std::cmp::Eq;
 use std::hash::{Hash, Hasher};
+use thiserror::Error;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Timestamp(f64);
 
 impl Timestamp {
@@ -261,11 +896,103 @@ impl Hash for Timestamp {
     }
 }
 
-#[derive(Eq, PartialEq, Hash)]
+#[derive(Clone, Eq, PartialEq, Hash)]
 struct Clip(Timestamp, Timestamp);
 
+#[derive(Eq, PartialEq, Hash)]
+struct Thumbnail(Timestamp, Option<u32>, ImageFormat);
+
+#[derive(Error, Debug)]
+enum ThumbnailError {
+    #[error("failed to decode frame: {0}")]
+    Ffmpeg(#[from] common::ffmpeg::Error),
+    #[error("failed to encode image: {0}")]
+    Image(#[from] image::ImageError),
+}
+
 impl From<(f64, f64)> for Clip {
     fn from(value: (f64, f64)) -> Self {
         Clip(Timestamp(value.0), Timestamp(value.1))
     }
 }
+
+impl Clip {
+    /// strong validator of the remuxed clip, only depends on the input file and the clip range
+    fn etag(&self, input: &Path) -> headers::ETag {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        input.hash(&mut hasher);
+        std::fs::metadata(input)
+            .map(|m| m.len())
+            .ok()
+            .hash(&mut hasher);
+        format!(
+            "\"{:x}-{:x}-{:x}\"",
+            hasher.finish(),
+            self.0.canonicalize(),
+            self.1.canonicalize()
+        )
+        .parse()
+        .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn decode<H: Header>(value: &'static str) -> H {
+        H::decode(&mut std::iter::once(&HeaderValue::from_static(value))).unwrap()
+    }
+
+    fn ranges(value: &'static str, len: u64) -> Vec<std::ops::Range<u64>> {
+        satisfiable_ranges(&decode(value), len)
+    }
+
+    #[test]
+    fn ranges_are_clamped_to_the_file() {
+        assert_eq!(ranges("bytes=0-499", 1000), [0..500]);
+        assert_eq!(ranges("bytes=900-1999", 1000), [900..1000]);
+        assert_eq!(ranges("bytes=500-", 1000), [500..1000]);
+    }
+
+    #[test]
+    fn suffix_ranges_count_from_the_end() {
+        assert_eq!(ranges("bytes=-100", 1000), [900..1000]);
+        assert_eq!(ranges("bytes=0-0,-1", 1000), [0..1, 999..1000]);
+    }
+
+    #[test]
+    fn overlapping_ranges_are_served_as_requested() {
+        assert_eq!(ranges("bytes=0-499,400-599", 1000), [0..500, 400..600]);
+    }
+
+    #[test]
+    fn ranges_past_the_end_are_unsatisfiable() {
+        assert!(ranges("bytes=1000-1999", 1000).is_empty());
+        assert_eq!(ranges("bytes=2000-2999,0-9", 1000), [0..10]);
+    }
+
+    #[test]
+    fn stale_if_range_serves_the_whole_clip() {
+        let etag: headers::ETag = "\"current\"".parse().unwrap();
+        let range: headers::Range = decode("bytes=0-9");
+
+        assert_eq!(
+            requested_ranges(Some(&range), None, &etag, 1000),
+            Some(vec![0..10])
+        );
+        assert_eq!(
+            requested_ranges(Some(&range), Some(&decode("\"current\"")), &etag, 1000),
+            Some(vec![0..10])
+        );
+        assert_eq!(
+            requested_ranges(Some(&range), Some(&decode("\"stale\"")), &etag, 1000),
+            None
+        );
+        assert_eq!(
+            requested_ranges(None, Some(&decode("\"current\"")), &etag, 1000),
+            None
+        );
+    }
+}
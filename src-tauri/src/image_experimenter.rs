@@ -1,12 +1,15 @@
 use crate::pixels_to_base64_image::pixels_to_base64_image;
+use crate::tesseract_options;
+use common::ocr::{set_whitelist, TesseractOptions};
 use common::process_frame::{FilterType, ResizeAlg};
-use common::tesseract::Tesseract;
+use common::rule_pack::TESSERACT_LANGUAGE;
 use common::{SearchParam, SEARCH_PARAMS};
 use image::codecs::jpeg::JpegEncoder;
 use image::io::Reader as ImageReader;
 use image::{ColorType, EncodableLayout};
 use serde::Deserialize;
 use serde::Serialize;
+use tauri::AppHandle;
 use thiserror::Error;
 use tracing::error;
 
@@ -23,6 +26,7 @@ pub fn process_image(
     image_filter_type: ImageFilterType,
     scale_height: u32,
     resize_algorithm: ResizeAlgorithm,
+    app_handle: AppHandle,
 ) -> Result<ProcessImageResult, ProcessImageError> {
     let mut img = ImageReader::open(image)?.decode()?;
 
@@ -57,7 +61,9 @@ pub fn process_image(
         resize_algorithm.into(),
     );
 
-    let ocr_result = recognize(&scaled, dst_width as i32, scale_height as i32);
+    // the experimenter shows what the default modes read
+    let options = tesseract_options(&app_handle, None, None).expect("the default modes are valid");
+    let ocr_result = recognize(&options, &scaled, dst_width as i32, scale_height as i32)?;
 
     let mut bytes: Vec<u8> = Vec::new();
     let mut writer = std::io::Cursor::new(&mut bytes);
@@ -71,13 +77,20 @@ pub fn process_image(
     })
 }
 
-fn recognize(frame_data: &[u8], width: i32, height: i32) -> String {
+fn recognize(
+    options: &TesseractOptions,
+    frame_data: &[u8],
+    width: i32,
+    height: i32,
+) -> Result<String, ProcessImageError> {
     let mut text = String::new();
     for search in SEARCH_PARAMS.iter() {
         match search {
             SearchParam::Text {
+                kind: _,
                 search_area,
                 patterns: _,
+                whitelist,
                 clip_length_after: _,
                 clip_length_before: _,
                 timeout: _timeout,
@@ -92,8 +105,8 @@ fn recognize(frame_data: &[u8], width: i32, height: i32) -> String {
 
                 // info!("search_area left: {left}, top: {top}, width: {inner_width}, height: {inner_height}");
 
-                let mut tess = Tesseract::new(None, Some("eng"))
-                    .unwrap()
+                let tess = options.build(TESSERACT_LANGUAGE)?;
+                let mut tess = set_whitelist(tess, *whitelist)?
                     .set_frame(frame_data, width, height, 3, width * 3)
                     .unwrap()
                     .set_rectangle(left, top, inner_width, inner_height)
@@ -108,10 +121,13 @@ fn recognize(frame_data: &[u8], width: i32, height: i32) -> String {
 
                 text += format!("\n {result}").as_str();
             }
-            SearchParam::AveragePixelValue { .. } => {}
+            SearchParam::Template { .. }
+            | SearchParam::ColorRegion { .. }
+            | SearchParam::StateEntered { .. }
+            | SearchParam::AveragePixelValue { .. } => {}
         }
     }
-    text
+    Ok(text)
 }
 
 #[derive(Serialize, Deserialize)]
@@ -173,6 +189,10 @@ pub enum ProcessImageError {
     ImageError(#[from] image::ImageError),
     #[error("failed to convert image to rgb8")]
     Rgb8ConversionError,
+    #[error("tesseract language not available")]
+    TesseractError(#[from] common::tesseract::InitializeError),
+    #[error("invalid tesseract whitelist")]
+    WhitelistError(#[from] common::tesseract::SetVariableError),
 }
 
 // we must manually implement serde::Serialize
@@ -3,26 +3,31 @@
 
 use std::convert::Into;
 use std::path::PathBuf;
-use std::sync::{Mutex, RwLock};
+use std::sync::Arc;
 
-use tauri::{AppHandle, Emitter};
+use tauri::{AppHandle, Emitter, Manager, State};
 use tracing::info;
 use tracing_subscriber::fmt;
 use tracing_subscriber::fmt::MakeWriter;
 use tracing_subscriber::util::SubscriberInitExt;
 
 use common::channel::unbounded;
-use common::{ProcessResult, VideoProcessor};
+use common::ocr::TesseractOptions;
+use common::project_file::{ExportSettings, PROJECT_EXTENSION};
+use common::{DetectionArgs, EventKind, ProcessResult, VideoProcessor, DEFAULT_MULTI_KILL_WINDOW};
 use image_experimenter::process_image;
 
-use crate::file_server::{get_file_server_address, serve, CLIP_CACHE};
+use crate::batch_queue::process_batch;
+use crate::file_server::{get_file_server_address, serve};
+use crate::project::{OpenedProject, ProjectInfo, Projects};
+use crate::sprite_sheet::spawn_sprite_generation;
 
+mod batch_queue;
 mod file_server;
 mod image_experimenter;
 mod pixels_to_base64_image;
-
-static VIDEO_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
-static KEYFRAMES: RwLock<Option<Box<[f64]>>> = RwLock::new(None);
+mod project;
+mod sprite_sheet;
 
 // Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
 #[tauri::command]
@@ -30,61 +35,215 @@ fn greet(name: &str) {
     info!("Hello, {}! You've been greeted from Rust!", name)
 }
 
+/// Opens `input` as a new project, or switches to it if it is already open.
+/// File server urls are relative to the project id
+#[tauri::command]
+fn open_input(input: String, projects: State<Arc<Projects>>) -> ProjectInfo {
+    let id = projects.open(&PathBuf::from(input));
+    projects.info(&id).unwrap()
+}
+
+#[tauri::command]
+fn switch_project(project: String, projects: State<Arc<Projects>>) -> Result<ProjectInfo, String> {
+    if !projects.switch(&project) {
+        return Err(format!("unknown project {project}"));
+    }
+    Ok(projects.info(&project).unwrap())
+}
+
+#[tauri::command]
+fn current_project(projects: State<Arc<Projects>>) -> Option<ProjectInfo> {
+    projects.info(&projects.current()?)
+}
+
+#[tauri::command]
+fn list_projects(projects: State<Arc<Projects>>) -> Vec<ProjectInfo> {
+    projects.list()
+}
+
+#[tauri::command]
+fn close_project(project: String, projects: State<Arc<Projects>>) {
+    projects.close(&project);
+}
+
+/// Stores the user-edited clips so they survive switching projects
+#[tauri::command]
+fn set_clips(
+    project: String,
+    clips: Vec<[f64; 2]>,
+    projects: State<Arc<Projects>>,
+) -> Result<(), String> {
+    let project = projects
+        .get(&project)
+        .ok_or_else(|| format!("unknown project {project}"))?;
+    project.set_clips(clips);
+    Ok(())
+}
+
+/// Saves the project to a `.eclipproj` file
+#[tauri::command(async)]
+fn save_project(
+    project: String,
+    path: String,
+    export: ExportSettings,
+    projects: State<Arc<Projects>>,
+) -> Result<(), String> {
+    let project = projects
+        .get(&project)
+        .ok_or_else(|| format!("unknown project {project}"))?;
+    let path = PathBuf::from(path).with_extension(PROJECT_EXTENSION);
+    project
+        .to_file(export)
+        .save(&path)
+        .map_err(|err| format!("failed to save project: {err}"))
+}
+
+/// Opens a `.eclipproj` file without reprocessing its input
+#[tauri::command(async)]
+fn open_project(
+    path: String,
+    app_handle: AppHandle,
+    projects: State<Arc<Projects>>,
+) -> Result<OpenedProject, String> {
+    let opened = projects
+        .open_file(&PathBuf::from(path))
+        .map_err(|err| format!("failed to open project: {err}"))?;
+    // the sprites may have been deleted or never generated on this machine
+    if let Some(result) = &opened.project.result {
+        spawn_sprite_generation(
+            opened.project.input.clone(),
+            result.keyframes.clone(),
+            result.input_duration,
+            app_handle,
+        );
+    }
+    Ok(opened)
+}
+
 #[tauri::command(async)]
+#[allow(clippy::too_many_arguments)]
 fn process(
-    input: String,
+    project: String,
     threads: usize,
     include_assists: bool,
     include_spectating: bool,
+    include_deaths: bool,
     elim_clip_duration: f64,
+    pre_roll: Vec<(EventKind, f64)>,
+    post_roll: Vec<(EventKind, f64)>,
+    multi_kills_only: bool,
     hw_accel: bool,
+    // tesseract page segmentation and engine modes, `None` for the defaults
+    psm: Option<u8>,
+    oem: Option<u8>,
+    // seconds without new data after which a recording that is still being written is
+    // considered finished, `None` for finished recordings
+    follow: Option<f64>,
     app_handle: AppHandle,
-) -> ProcessResult {
-    let path: PathBuf = input.parse().unwrap();
-    {
-        let mut p = VIDEO_FILE.lock().unwrap();
-        *p = Some(path.clone());
-    }
+    projects: State<Arc<Projects>>,
+) -> Result<ProcessResult, String> {
+    let project = projects
+        .get(&project)
+        .ok_or_else(|| format!("unknown project {project}"))?;
+    let path = project.input.clone();
     let (progress_tx, progress_rx) = unbounded();
     let (frame_tx, frame_rx) = unbounded();
     let args = common::Args {
-        input: path,
+        input: path.clone(),
+        detection: DetectionArgs {
+            include_assists,
+            include_spectating,
+            include_deaths,
+            elim_clip_duration,
+            pre_roll,
+            post_roll,
+            multi_kill_window: DEFAULT_MULTI_KILL_WINDOW,
+            multi_kills_only,
+            tesseract: tesseract_options(&app_handle, psm, oem)?,
+            templates: resource_dir(&app_handle, "templates"),
+        },
         threads,
-        include_assists,
-        elim_clip_duration,
-        include_spectating,
+        cache_dir: None,
     };
     let video_processor = VideoProcessor::open(args, progress_tx, frame_tx);
-    {
-        let mut lock = KEYFRAMES.write().unwrap();
-        *lock = Some(video_processor.keyframes.clone());
-    }
-    let join_handle = std::thread::spawn(move || video_processor.process(hw_accel));
+    let keyframes = video_processor.keyframes.clone();
+    let video_duration = video_processor.video_duration;
+    project.set_keyframes(keyframes.clone());
+    let join_handle = std::thread::spawn(move || match follow {
+        Some(idle_timeout) => video_processor
+            .process_growing(hw_accel, std::time::Duration::from_secs_f64(idle_timeout)),
+        None => video_processor.process(hw_accel),
+    });
 
     while let Ok(progress) = progress_rx.recv() {
         app_handle.emit("progress", progress).ok();
         while let Ok(frame) = frame_rx.try_recv() {
             let pts = frame.pts;
-            CLIP_CACHE.insert_frame(frame);
+            project.cache.insert_frame(frame);
             app_handle.emit("preview_frame", pts).ok();
         }
     }
     while let Ok(frame) = frame_rx.recv() {
         let pts = frame.pts;
-        CLIP_CACHE.insert_frame(frame);
+        project.cache.insert_frame(frame);
         app_handle.emit("preview_frame", pts).ok();
     }
-    join_handle.join().unwrap()
+    let result = join_handle.join().unwrap();
+    project.set_result(result.clone());
+    // a followed recording is only complete now
+    let (keyframes, video_duration) = match follow {
+        Some(_) => (result.keyframes.clone(), result.input_duration),
+        None => (keyframes, video_duration),
+    };
+    spawn_sprite_generation(path, keyframes, video_duration, app_handle);
+    Ok(result)
 }
 
 #[tauri::command(async)]
-fn write_clips(clips: Vec<[f64; 2]>, keyframes: Vec<f64>, path: String) {
-    let input: PathBuf = {
-        let lock = VIDEO_FILE.lock().unwrap();
-        Option::clone(&lock).unwrap_or_default()
-    };
+fn write_clips(
+    project: String,
+    clips: Vec<[f64; 2]>,
+    path: String,
+    projects: State<Arc<Projects>>,
+) -> Result<(), String> {
+    let project = projects
+        .get(&project)
+        .ok_or_else(|| format!("unknown project {project}"))?;
     let output: PathBuf = path.parse().unwrap();
-    common::write_clips(&input, &output, &clips, &keyframes);
+    common::write_clips(&project.input, &output, &clips, &project.keyframes());
+    Ok(())
+}
+
+/// A directory bundled with the app, `None` if it isn't there
+pub fn resource_dir(app_handle: &AppHandle, name: &str) -> Option<PathBuf> {
+    app_handle
+        .path()
+        .resource_dir()
+        .ok()
+        .map(|dir| dir.join(name))
+        .filter(|dir| dir.is_dir())
+}
+
+/// Uses the `tessdata` directory bundled with the app if there is one,
+/// otherwise tesseract looks in its default location.
+/// `psm` and `oem` are numbered like the tesseract cli options
+pub fn tesseract_options(
+    app_handle: &AppHandle,
+    psm: Option<u8>,
+    oem: Option<u8>,
+) -> Result<TesseractOptions, String> {
+    if psm.is_some_and(|psm| psm > 13) {
+        return Err(format!("invalid page segmentation mode {}", psm.unwrap()));
+    }
+    if oem.is_some_and(|oem| oem > 3) {
+        return Err(format!("invalid OCR engine mode {}", oem.unwrap()));
+    }
+    Ok(TesseractOptions {
+        tessdata: resource_dir(app_handle, "tessdata"),
+        psm,
+        oem,
+        ..Default::default()
+    })
 }
 
 #[tauri::command]
@@ -97,7 +256,8 @@ fn main() {
         .enable_all()
         .build()
         .unwrap();
-    let handle = rt.spawn(serve());
+    let projects = Arc::new(Projects::default());
+    let handle = rt.spawn(serve(projects.clone()));
 
     tauri::Builder::default()
         .plugin(tauri_plugin_fs::init())
@@ -106,12 +266,22 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             greet,
             process,
+            process_batch,
             max_thread_count,
             process_image,
             get_file_server_address,
+            open_input,
+            switch_project,
+            current_project,
+            list_projects,
+            close_project,
+            set_clips,
+            save_project,
+            open_project,
             write_clips,
         ])
-        .setup(|app| {
+        .setup(move |app| {
+            app.manage(projects);
             let handle = app.handle();
             let subscriber = fmt()
                 .with_writer(LogWriter {
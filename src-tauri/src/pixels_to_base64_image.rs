@@ -1,6 +1,11 @@
 use base64::prelude::BASE64_STANDARD;
 use base64::Engine;
 use bytes::Bytes;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{ColorType, ImageEncoder, ImageResult};
+use serde::Deserialize;
 
 #[rustfmt::skip]
 const BMP_HEADER: &[u8] = &[
@@ -49,3 +54,52 @@ pub fn pixels_to_base64_image(pixels: &mut [u8], width: u32, height: u32) -> Str
 
     format!("data:image/bmp;base64,{}", BASE64_STANDARD.encode(bytes))
 }
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    #[default]
+    Jpeg,
+    Png,
+    Webp,
+}
+
+impl ImageFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+            ImageFormat::Webp => "image/webp",
+        }
+    }
+}
+
+/// encode rgb24 pixels
+pub fn pixels_to_image(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    format: ImageFormat,
+) -> ImageResult<Bytes> {
+    let mut bytes = Vec::new();
+    match format {
+        ImageFormat::Jpeg => JpegEncoder::new_with_quality(&mut bytes, 90).write_image(
+            pixels,
+            width,
+            height,
+            ColorType::Rgb8,
+        )?,
+        ImageFormat::Png => {
+            PngEncoder::new(&mut bytes).write_image(pixels, width, height, ColorType::Rgb8)?
+        }
+        // lossy webp encoding needs libwebp
+        ImageFormat::Webp => WebPEncoder::new_lossless(&mut bytes).write_image(
+            pixels,
+            width,
+            height,
+            ColorType::Rgb8,
+        )?,
+    }
+
+    Ok(Bytes::from(bytes))
+}
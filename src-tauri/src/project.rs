@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use serde::Serialize;
+
+use common::match_segment::segment_matches;
+use common::multi_kill::clip_streaks;
+use common::ocr::TesseractOptions;
+use common::project_file::{
+    ExportSettings, ProjectClip, ProjectFile, ProjectFileError, ProjectInput,
+};
+use common::{clips_from_detections, Args, DetectionArgs, ProcessResult, VideoDecoder};
+
+use crate::file_server::{evict_clips, Cache};
+
+/// An opened recording with everything needed to preview and export it without reprocessing
+pub struct Project {
+    pub input: PathBuf,
+    keyframes: RwLock<Box<[f64]>>,
+    /// result of the last processing run
+    result: RwLock<Option<ProcessResult>>,
+    /// clips as edited by the user, initially the detected clips
+    clips: RwLock<Option<Vec<[f64; 2]>>>,
+    pub cache: Cache,
+}
+
+impl Project {
+    fn new(input: PathBuf) -> Project {
+        Project {
+            input,
+            keyframes: RwLock::new(Box::new([])),
+            result: RwLock::new(None),
+            clips: RwLock::new(None),
+            cache: Cache::new(),
+        }
+    }
+
+    pub fn keyframes(&self) -> Box<[f64]> {
+        self.keyframes.read().unwrap().clone()
+    }
+
+    pub fn set_keyframes(&self, keyframes: Box<[f64]>) {
+        *self.keyframes.write().unwrap() = keyframes;
+    }
+
+    /// length of the input in seconds, read from the file if it hasn't been processed yet
+    pub fn duration(&self) -> Result<f64, common::ffmpeg::Error> {
+        if let Some(result) = self.result.read().unwrap().as_ref() {
+            return Ok(result.input_duration);
+        }
+        Ok(VideoDecoder::open(&self.input, false)?.video_duration())
+    }
+
+    pub fn result(&self) -> Option<ProcessResult> {
+        self.result.read().unwrap().clone()
+    }
+
+    /// replaces the edited clips with the detected ones
+    pub fn set_result(&self, result: ProcessResult) {
+        self.set_keyframes(result.keyframes.clone());
+        *self.clips.write().unwrap() = Some(result.clips.to_vec());
+        *self.result.write().unwrap() = Some(result);
+    }
+
+    pub fn clips(&self) -> Option<Vec<[f64; 2]>> {
+        self.clips.read().unwrap().clone()
+    }
+
+    pub fn set_clips(&self, clips: Vec<[f64; 2]>) {
+        *self.clips.write().unwrap() = Some(clips);
+    }
+
+    /// the project as it is saved to a `.eclipproj` file
+    pub fn to_file(&self, export: ExportSettings) -> ProjectFile {
+        let result = self.result();
+        let input = ProjectInput {
+            path: self.input.clone(),
+            keyframes: self.keyframes(),
+            duration: result.as_ref().map(|r| r.input_duration),
+            events: result.map(|r| r.detections).unwrap_or_default(),
+        };
+        let clips = self
+            .clips()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|[start, end]| ProjectClip {
+                source: 0,
+                start,
+                end,
+            })
+            .collect();
+        ProjectFile::new(vec![input], clips, export)
+    }
+}
+
+/// What the frontend needs to show a project
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectInfo {
+    pub id: String,
+    pub input: PathBuf,
+    /// `None` if the project hasn't been processed yet
+    pub result: Option<ProcessResult>,
+    pub clips: Option<Vec<[f64; 2]>>,
+}
+
+/// A project opened from a `.eclipproj` file
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenedProject {
+    #[serde(flatten)]
+    pub project: ProjectInfo,
+    pub export: ExportSettings,
+}
+
+/// All opened projects, managed as tauri state and shared with the file server
+#[derive(Default)]
+pub struct Projects {
+    projects: Mutex<BTreeMap<String, Arc<Project>>>,
+    current: Mutex<Option<String>>,
+}
+
+impl Projects {
+    /// returns the id of the project for `input`, creating it if it doesn't exist yet,
+    /// and makes it the current project
+    pub fn open(&self, input: &Path) -> String {
+        let mut projects = self.projects.lock().unwrap();
+        let id = match projects.iter().find(|(_, p)| p.input == input) {
+            Some((id, _)) => id.clone(),
+            None => {
+                let id = loop {
+                    let id = format!("{:016x}", rand::random::<u64>());
+                    if !projects.contains_key(&id) {
+                        break id;
+                    }
+                };
+                let project = Arc::new(Project::new(input.to_path_buf()));
+                let extracting = project.clone();
+                std::thread::spawn(move || extracting.cache.extract_waveform(&extracting.input));
+                projects.insert(id.clone(), project);
+                id
+            }
+        };
+        *self.current.lock().unwrap() = Some(id.clone());
+        id
+    }
+
+    /// Opens the input of a project file with its events and clips and makes it the current project.
+    /// A project can only preview a single recording, files with more inputs can be exported with
+    /// the cli
+    pub fn open_file(&self, path: &Path) -> Result<OpenedProject, ProjectFileError> {
+        let file = ProjectFile::open(path)?;
+        if file.inputs.len() > 1 {
+            return Err(ProjectFileError::MultipleInputs(file.inputs.len()));
+        }
+        let input = file.inputs.first().ok_or(ProjectFileError::NoInputs)?;
+        let id = self.open(&input.path);
+        let project = self.get(&id).unwrap();
+
+        if !input.keyframes.is_empty() {
+            let args = Args {
+                input: input.path.clone(),
+                detection: DetectionArgs {
+                    include_assists: file.export.include_assists,
+                    include_spectating: file.export.include_spectating,
+                    include_deaths: file.export.include_deaths,
+                    elim_clip_duration: file.export.elim_clip_duration,
+                    pre_roll: file.export.pre_roll.clone(),
+                    post_roll: file.export.post_roll.clone(),
+                    multi_kill_window: file.export.multi_kill_window,
+                    multi_kills_only: file.export.multi_kills_only,
+                    // only needed to detect events, they are stored in the project
+                    tesseract: TesseractOptions::default(),
+                    templates: None,
+                },
+                threads: common::thread_count(),
+                cache_dir: None,
+            };
+            let clips = clips_from_detections(&input.events, &input.keyframes, &args);
+            let input_duration = input
+                .duration
+                .or(input.keyframes.last().copied())
+                .unwrap_or_default();
+            project.set_result(ProcessResult {
+                streaks: clip_streaks(&clips, &input.events, args.detection.multi_kill_window),
+                matches: segment_matches(&input.events, input_duration),
+                clips,
+                detections: input.events.clone(),
+                keyframes: input.keyframes.clone(),
+                input_duration,
+            });
+        }
+        project.set_clips(
+            file.clips
+                .iter()
+                .map(|clip| [clip.start, clip.end])
+                .collect(),
+        );
+
+        Ok(OpenedProject {
+            project: self.info(&id).unwrap(),
+            export: file.export,
+        })
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Project>> {
+        self.projects.lock().unwrap().get(id).cloned()
+    }
+
+    pub fn current(&self) -> Option<String> {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// makes `id` the current project, returns false if it doesn't exist
+    pub fn switch(&self, id: &str) -> bool {
+        let exists = self.projects.lock().unwrap().contains_key(id);
+        if exists {
+            *self.current.lock().unwrap() = Some(id.to_string());
+        }
+        exists
+    }
+
+    pub fn info(&self, id: &str) -> Option<ProjectInfo> {
+        let project = self.get(id)?;
+        Some(ProjectInfo {
+            id: id.to_string(),
+            input: project.input.clone(),
+            result: project.result(),
+            clips: project.clips(),
+        })
+    }
+
+    pub fn list(&self) -> Vec<ProjectInfo> {
+        let ids: Vec<String> = self.projects.lock().unwrap().keys().cloned().collect();
+        ids.iter().filter_map(|id| self.info(id)).collect()
+    }
+
+    /// drops the project and its caches, requests that are still running keep their reference
+    pub fn close(&self, id: &str) {
+        self.projects.lock().unwrap().remove(id);
+        evict_clips(id);
+        let mut current = self.current.lock().unwrap();
+        if current.as_deref() == Some(id) {
+            *current = None;
+        }
+    }
+}
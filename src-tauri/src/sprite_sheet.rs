@@ -0,0 +1,200 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+use thiserror::Error;
+use tracing::{error, info};
+
+use common::process_frame::{scale_frame, FilterType, ResizeAlg};
+use common::VideoDecoder;
+
+use crate::pixels_to_base64_image::{pixels_to_image, ImageFormat};
+
+/// seconds between thumbnails
+pub const SPRITE_INTERVAL: f64 = 10.;
+const TILE_HEIGHT: u32 = 90;
+const COLUMNS: usize = 10;
+const ROWS: usize = 10;
+pub const INDEX_FILE: &str = "sprites.vtt";
+/// written last, sprites are only complete if this exists and matches the input
+const INFO_FILE: &str = "info.json";
+
+#[derive(Serialize, Deserialize, PartialEq)]
+struct SpriteInfo {
+    file_size: u64,
+    interval: f64,
+    tile_height: u32,
+}
+
+/// sprite sheets are cached next to the .eclipper cache
+pub fn sprite_dir(input: &Path) -> PathBuf {
+    input.with_extension("eclipper-sprites")
+}
+
+/// whether the sprites for `input` have already been generated
+pub fn sprites_exist(input: &Path) -> bool {
+    let Ok(metadata) = std::fs::metadata(input) else {
+        return false;
+    };
+    let Ok(info) = std::fs::read(sprite_dir(input).join(INFO_FILE)) else {
+        return false;
+    };
+    serde_json::from_slice::<SpriteInfo>(&info).is_ok_and(|info| {
+        info == SpriteInfo {
+            file_size: metadata.len(),
+            interval: SPRITE_INTERVAL,
+            tile_height: TILE_HEIGHT,
+        }
+    })
+}
+
+/// returns the path of a generated sprite file, `name` must be the index or one of the sheets
+pub fn sprite_file(input: &Path, name: &str) -> Option<PathBuf> {
+    let is_sheet = name
+        .strip_prefix("sprite_")
+        .and_then(|name| name.strip_suffix(".jpg"))
+        .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+    if name != INDEX_FILE && !is_sheet {
+        return None;
+    }
+    Some(sprite_dir(input).join(name))
+}
+
+/// Generates the sprite sheets on a background thread and emits `sprites_ready` when done.
+pub fn spawn_sprite_generation(
+    input: PathBuf,
+    keyframes: Box<[f64]>,
+    duration: f64,
+    app_handle: AppHandle,
+) {
+    std::thread::spawn(move || {
+        if !sprites_exist(&input) {
+            if let Err(err) = generate_sprites(&input, &keyframes, duration) {
+                error!("failed to generate sprites: {err}");
+                return;
+            }
+        }
+        app_handle.emit("sprites_ready", ()).ok();
+    });
+}
+
+/// Decodes one keyframe every [`SPRITE_INTERVAL`] seconds and packs them into jpeg sprite sheets
+/// with a WebVTT index
+pub fn generate_sprites(
+    input: &Path,
+    keyframes: &[f64],
+    duration: f64,
+) -> Result<(), SpriteSheetError> {
+    let dir = sprite_dir(input);
+    std::fs::create_dir_all(&dir)?;
+    // remove stale info so a partially written cache is never used
+    std::fs::remove_file(dir.join(INFO_FILE)).ok();
+
+    let mut decoder = VideoDecoder::open(&input, false)?;
+    decoder.set_keyframes_only(true);
+
+    let scale_factor = decoder.height() as f64 / TILE_HEIGHT as f64;
+    let tile_width = (decoder.width() as f64 / scale_factor) as u32;
+    let tile_height = TILE_HEIGHT;
+    let sheet_width = tile_width as usize * COLUMNS;
+    let sheet_height = tile_height as usize * ROWS;
+    let tiles_per_sheet = COLUMNS * ROWS;
+
+    let tile_count = (duration / SPRITE_INTERVAL).ceil().max(1.) as usize;
+    info!("generating {tile_count} sprite tiles");
+
+    let mut index = String::from("WEBVTT\n\n");
+    let mut sheet = vec![0_u8; sheet_width * sheet_height * 3];
+    let mut tile: Option<(f64, Vec<u8>)> = None;
+
+    for i in 0..tile_count {
+        let time = i as f64 * SPRITE_INTERVAL;
+        let keyframe = keyframes
+            .iter()
+            .rev()
+            .copied()
+            .find(|k| *k <= time)
+            .or(keyframes.first().copied())
+            .unwrap_or(time);
+
+        // consecutive tiles may share a keyframe when keyframes are far apart
+        if !tile.as_ref().is_some_and(|(t, _)| *t == keyframe) {
+            let frame = decoder.extract_frame(keyframe)?;
+            let pixels = scale_frame(
+                &frame.pixels,
+                frame.width as i32,
+                frame.height as i32,
+                tile_height,
+                ResizeAlg::Convolution(FilterType::Bilinear),
+            );
+            tile = Some((keyframe, pixels));
+        }
+        let (_, pixels) = tile.as_ref().unwrap();
+
+        let sheet_index = i / tiles_per_sheet;
+        let column = i % COLUMNS;
+        let row = (i % tiles_per_sheet) / COLUMNS;
+        let x = column * tile_width as usize;
+        let y = row * tile_height as usize;
+        for (line, src) in pixels.chunks_exact(tile_width as usize * 3).enumerate() {
+            let offset = ((y + line) * sheet_width + x) * 3;
+            sheet[offset..offset + src.len()].copy_from_slice(src);
+        }
+
+        let end = (time + SPRITE_INTERVAL).min(duration.max(time));
+        writeln!(
+            index,
+            "{} --> {}\nsprite_{sheet_index}.jpg#xywh={x},{y},{tile_width},{tile_height}\n",
+            format_vtt_time(time),
+            format_vtt_time(end)
+        )
+        .unwrap();
+
+        let is_last_tile = i + 1 == tile_count;
+        if (i + 1) % tiles_per_sheet == 0 || is_last_tile {
+            let image = pixels_to_image(
+                &sheet,
+                sheet_width as u32,
+                sheet_height as u32,
+                ImageFormat::Jpeg,
+            )?;
+            std::fs::write(dir.join(format!("sprite_{sheet_index}.jpg")), image)?;
+            sheet.fill(0);
+        }
+    }
+
+    std::fs::write(dir.join(INDEX_FILE), index)?;
+    let info = SpriteInfo {
+        file_size: std::fs::metadata(input)?.len(),
+        interval: SPRITE_INTERVAL,
+        tile_height: TILE_HEIGHT,
+    };
+    std::fs::write(dir.join(INFO_FILE), serde_json::to_vec(&info)?)?;
+    info!("sprites written to {}", dir.display());
+
+    Ok(())
+}
+
+fn format_vtt_time(secs: f64) -> String {
+    let millis = (secs * 1000.).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        (millis / 60_000) % 60,
+        (millis / 1000) % 60,
+        millis % 1000
+    )
+}
+
+#[derive(Error, Debug)]
+pub enum SpriteSheetError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to decode frame: {0}")]
+    Ffmpeg(#[from] common::ffmpeg::Error),
+    #[error("failed to encode image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("failed to write sprite info: {0}")]
+    Json(#[from] serde_json::Error),
+}
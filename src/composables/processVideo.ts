@@ -9,16 +9,32 @@ const state = reactive({
     processing: false,
     includeAssists: true,
     includeSpectating: false,
+    includeDeaths: false,
+    // leave out single eliminations
+    multiKillsOnly: false,
     elimClipDuration: 4.0,
+    // seconds before and after events by kind, missing kinds use their defaults
+    preRoll: {} as Record<string, number | null>,
+    postRoll: {} as Record<string, number | null>,
     hwAccel: true,
+    // tesseract page segmentation and engine modes, null for the defaults
+    psm: null as number | null,
+    oem: null as number | null,
     progress: 0,
     speed: 0,
     elapsed: 0,
     clips: [] as Array<[number, number]>,
     keyframes: [] as Array<number>,
     inputDuration: null as number | null,
+    // id of the opened project of the input, previews are served relative to it
+    project: null as string | null,
 });
 
+// the set clip lengths as the [kind, seconds] pairs the backend expects
+function clipLengths(lengths: Record<string, number | null>): Array<[string, number]> {
+    return Object.entries(lengths).filter((entry): entry is [string, number] => entry[1] !== null);
+}
+
 export default function useProcessVideo() {
     const input = useInput();
     const threads = useThreads();
@@ -37,9 +53,60 @@ export default function useProcessVideo() {
             state.inputDuration = Number(inputDuration);
         }
     }
+    if (state.project === null && input.value !== null) {
+        invoke<{ id: string }>('open_input', {input: input.value}).then((project) => {
+            state.project = project.id;
+        });
+    }
 
     return {
         state,
+        // restores a saved .eclipproj without processing the input again
+        async openProject(path: string) {
+            let project = await invoke<{
+                id: string,
+                input: string,
+                result: { clips: Array<[number, number]>, keyframes: Array<number>, input_duration: number } | null,
+                clips: Array<[number, number]> | null,
+                export: { includeAssists: boolean, includeSpectating: boolean, includeDeaths?: boolean, multiKillsOnly?: boolean, elimClipDuration: number, preRoll?: Array<[string, number]>, postRoll?: Array<[string, number]> },
+            }>('open_project', {path});
+            state.project = project.id;
+            input.value = project.input;
+            state.includeAssists = project.export.includeAssists;
+            state.includeSpectating = project.export.includeSpectating;
+            state.includeDeaths = project.export.includeDeaths ?? false;
+            state.multiKillsOnly = project.export.multiKillsOnly ?? false;
+            state.elimClipDuration = project.export.elimClipDuration;
+            state.preRoll = Object.fromEntries(project.export.preRoll ?? []);
+            state.postRoll = Object.fromEntries(project.export.postRoll ?? []);
+            state.elapsed = 0;
+            state.clips = project.clips ?? project.result?.clips ?? [];
+            state.keyframes = project.result?.keyframes ?? [];
+            state.inputDuration = project.result?.input_duration ?? state.keyframes.slice(-1)[0] ?? null;
+            window.localStorage.setItem('clips', JSON.stringify(state.clips));
+            window.localStorage.setItem('keyframes', JSON.stringify(state.keyframes));
+            if (state.inputDuration !== null) {
+                window.localStorage.setItem('inputDuration', state.inputDuration.toString());
+            }
+        },
+        async saveProject(path: string, clips: Array<[number, number]>, output: string | null) {
+            if (state.project === null) return;
+            await invoke('set_clips', {project: state.project, clips});
+            await invoke('save_project', {
+                project: state.project,
+                path,
+                export: {
+                    output,
+                    includeAssists: state.includeAssists,
+                    includeSpectating: state.includeSpectating,
+                    includeDeaths: state.includeDeaths,
+                    multiKillsOnly: state.multiKillsOnly,
+                    elimClipDuration: state.elimClipDuration,
+                    preRoll: clipLengths(state.preRoll),
+                    postRoll: clipLengths(state.postRoll),
+                },
+            });
+        },
         async start() {
             // cannot start processing if already processing
             if (state.processing) {
@@ -49,13 +116,20 @@ export default function useProcessVideo() {
             state.speed = 0;
             state.processing = true;
             state.elapsed = 0;
+            state.project = (await invoke<{ id: string }>('open_input', {input: input.value})).id;
             let args = {
-                input: input.value,
+                project: state.project,
                 threads: threads.value,
                 includeAssists: state.includeAssists,
                 includeSpectating: state.includeSpectating,
+                includeDeaths: state.includeDeaths,
+                multiKillsOnly: state.multiKillsOnly,
                 elimClipDuration: state.elimClipDuration,
+                preRoll: clipLengths(state.preRoll),
+                postRoll: clipLengths(state.postRoll),
                 hwAccel: state.hwAccel,
+                psm: state.psm,
+                oem: state.oem,
             };
             console.log(args);
             let instant = new Date();